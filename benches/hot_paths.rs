@@ -0,0 +1,187 @@
+// Benchmarks of the engine's hot paths on fixed positions from seeded games:
+//   cargo bench [FILTER]
+// e.g. `cargo bench resolve_board` runs just one group. Criterion keeps the results of the last
+// run in `target/criterion`, so each optimization is compared with the run before it. With
+// `cargo bench --features metrics` the counters of the engine's work are reported after each
+// group (and measured with the benchmarks).
+use criterion::{black_box, criterion_group, criterion_main, BenchmarkId, Criterion};
+use roku_doku::ai::{ai_move, greedy_action};
+use roku_doku::board::{can_put_brick, possible_moves, put_brick};
+use roku_doku::dealer::{BrickQueue, SeededDealer};
+use roku_doku::game::{get_possible_moves, is_game_over, perform_action, perform_move, GameState, Rules};
+use roku_doku::metrics::{self, Metrics};
+use roku_doku::resolve::resolve_board;
+use roku_doku::Board;
+use std::time::Instant;
+
+// (seed, greedy moves played) - from the empty board to a crowded one
+const POSITIONS: [(u64, usize); 4] = [(1, 0), (2, 8), (3, 16), (4, 24)];
+
+const HAND_SIZES: [usize; 4] = [1, 2, 3, 4];
+
+// the position after a few greedy moves of a seeded game, with a full hand
+fn position(seed: u64, moves: usize, hand_size: usize) -> GameState {
+    let mut dealer = SeededDealer::new(seed);
+    let mut queue = BrickQueue::new(0);
+    let mut game_state = GameState::with_rules(Rules {
+        hand_size,
+        ..Rules::default()
+    });
+    for _ in 0..moves {
+        queue.refill(&mut game_state, &mut dealer);
+        assert!(!is_game_over(&game_state), "seed {} ends after less than {} moves", seed, moves);
+        let action = greedy_action(&game_state).unwrap().action;
+        game_state = perform_action(&game_state, &action).unwrap();
+    }
+    game_state.available_bricks.clear();
+    queue.refill(&mut game_state, &mut dealer);
+    game_state
+}
+
+fn positions() -> Vec<(String, GameState)> {
+    POSITIONS
+        .iter()
+        .map(|&(seed, moves)| (format!("seed {} after {} moves", seed, moves), position(seed, moves, 3)))
+        .collect()
+}
+
+// Counts a single group: the counters are reset once its positions are set up, then reported
+// after all its samples (warm-up included) with their rates over the time the group ran.
+fn start_metrics() -> Instant {
+    metrics::reset();
+    Instant::now()
+}
+
+fn report_metrics(group: &str, start: Instant) {
+    let metrics = metrics::snapshot();
+    // nothing is counted in groups skipped by the filter
+    if metrics != Metrics::default() {
+        println!("metrics of {}:\n{}\n", group, metrics.report(start.elapsed()));
+    }
+}
+
+fn bench_can_put_brick(c: &mut Criterion) {
+    let positions = positions();
+    let start = start_metrics();
+    let mut group = c.benchmark_group("can_put_brick");
+    for (name, game_state) in positions {
+        // every brick of the hand at every cell of the board
+        group.bench_function(name, |b| {
+            b.iter(|| {
+                let board = black_box(&game_state.board);
+                let mut count = 0;
+                for brick in &game_state.available_bricks {
+                    for pos in board.positions() {
+                        count += can_put_brick(board, brick.brick(), &pos) as usize;
+                    }
+                }
+                count
+            })
+        });
+    }
+    group.finish();
+    report_metrics("can_put_brick", start);
+}
+
+fn bench_possible_moves(c: &mut Criterion) {
+    let positions = positions();
+    let start = start_metrics();
+    let mut group = c.benchmark_group("possible_moves");
+    for (name, game_state) in positions {
+        group.bench_function(name, |b| {
+            b.iter(|| {
+                let board = black_box(&game_state.board);
+                game_state
+                    .available_bricks
+                    .iter()
+                    .map(|brick| possible_moves(board, brick.brick()).len())
+                    .sum::<usize>()
+            })
+        });
+    }
+    group.finish();
+    report_metrics("possible_moves", start);
+}
+
+fn bench_resolve_board(c: &mut Criterion) {
+    // the boards right after each possible move, before anything is cleared
+    let positions = positions()
+        .into_iter()
+        .map(|(name, game_state)| {
+            let boards = get_possible_moves(&game_state)
+                .iter()
+                .map(|m| put_brick(&game_state.board, m.brick.brick(), &m.pos).unwrap())
+                .collect::<Vec<Board>>();
+            (name, boards)
+        })
+        .collect::<Vec<_>>();
+    let start = start_metrics();
+    let mut group = c.benchmark_group("resolve_board");
+    for (name, boards) in positions {
+        group.bench_function(name, |b| {
+            b.iter(|| {
+                boards
+                    .iter()
+                    .map(|&board| resolve_board(black_box(board)).blocks_removed)
+                    .sum::<i32>()
+            })
+        });
+    }
+    group.finish();
+    report_metrics("resolve_board", start);
+}
+
+fn bench_perform_move(c: &mut Criterion) {
+    let positions = positions()
+        .into_iter()
+        .map(|(name, game_state)| {
+            let moves = get_possible_moves(&game_state);
+            (name, game_state, moves)
+        })
+        .collect::<Vec<_>>();
+    let start = start_metrics();
+    let mut group = c.benchmark_group("perform_move");
+    for (name, game_state, moves) in positions {
+        group.bench_function(name, |b| {
+            b.iter(|| {
+                moves
+                    .iter()
+                    .map(|m| perform_move(black_box(&game_state), &m.clone().into()).unwrap().points)
+                    .sum::<i32>()
+            })
+        });
+    }
+    group.finish();
+    report_metrics("perform_move", start);
+}
+
+fn bench_ai_move(c: &mut Criterion) {
+    let mut positions = vec![];
+    for &hand_size in &HAND_SIZES {
+        for &(seed, moves) in &POSITIONS[..2] {
+            let id = BenchmarkId::new(format!("hand of {}", hand_size), format!("seed {} after {} moves", seed, moves));
+            positions.push((id, position(seed, moves, hand_size)));
+        }
+    }
+    let start = start_metrics();
+    let mut group = c.benchmark_group("ai_move");
+    // whole searches take long, especially with bigger hands
+    group.sample_size(10);
+    for (id, game_state) in positions {
+        group.bench_with_input(id, &game_state, |b, game_state| {
+            b.iter(|| ai_move(black_box(game_state)).unwrap().score)
+        });
+    }
+    group.finish();
+    report_metrics("ai_move", start);
+}
+
+criterion_group!(
+    benches,
+    bench_can_put_brick,
+    bench_possible_moves,
+    bench_resolve_board,
+    bench_perform_move,
+    bench_ai_move
+);
+criterion_main!(benches);
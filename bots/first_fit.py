@@ -0,0 +1,41 @@
+#!/usr/bin/env python3
+# Example external bot for `roku_doku referee` - puts the first brick from the hand
+# in the first place it fits. See `src/referee.rs` for the protocol.
+import sys
+
+
+# a brick is its ID followed by its cells - only the cells are needed here
+def read_bricks(lines, count):
+    return [[tuple(map(int, cell.split(","))) for cell in next(lines).split()[1:]] for _ in range(count)]
+
+
+def first_fit(board, hand):
+    size = len(board)
+    for i, brick in enumerate(hand):
+        for y in range(size):
+            for x in range(size):
+                if all(0 <= x + dx < size and 0 <= y + dy < size and board[y + dy][x + dx] == "."
+                       for dx, dy in brick):
+                    return "{} {}{}".format(i + 1, chr(ord("a") + x), y + 1)
+    # no legal move - the game would be over already
+    return "1 a1"
+
+
+def main():
+    lines = (line.strip() for line in sys.stdin)
+    board, hand = [], []
+    for line in lines:
+        name, _, value = line.partition(" ")
+        if name == "board":
+            board = [next(lines) for _ in range(int(value))]
+        elif name == "hand":
+            hand = read_bricks(lines, int(value))
+        elif name in ("next", "held"):
+            read_bricks(lines, int(value))
+        elif name == "go":
+            print(first_fit(board, hand), flush=True)
+        elif name == "gameover":
+            return
+
+
+main()
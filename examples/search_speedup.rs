@@ -0,0 +1,86 @@
+// Compares the speed of the bot's search core (`src/search.rs`) with the reference search it
+// replaced (`ai::reference_search`) on positions from seeded games:
+//   cargo run --release --example search_speedup [POSITIONS] [heuristic|mlp]
+// Both searches have to pick the same moves. With `RAYON_NUM_THREADS=1` they are compared on
+// a single thread. `mlp` evaluates the leaves with an (untrained) network - the reference
+// evaluates them in batches, the search core one by one.
+use roku_doku::ai::{greedy_action, reference_search, Evaluator, MAX_SEARCH_DEPTH};
+use roku_doku::dealer::{BrickQueue, SeededDealer};
+use roku_doku::game::{is_game_over, perform_action, GameState};
+use roku_doku::mlp::{board_features, Mlp};
+use roku_doku::search::{best_move, Parallelism};
+use std::time::{Duration, Instant};
+
+// the position after a few greedy moves of a seeded game - boards get fuller later in the game
+fn position(seed: u64) -> Option<GameState> {
+    let mut dealer = SeededDealer::new(seed);
+    let mut queue = BrickQueue::new(0);
+    let mut game_state = GameState::new();
+    for _ in 0..seed % 10 * 3 {
+        queue.refill(&mut game_state, &mut dealer);
+        if is_game_over(&game_state) {
+            return None;
+        }
+        let action = greedy_action(&game_state).ok()?.action;
+        game_state = perform_action(&game_state, &action).ok()?;
+    }
+    queue.refill(&mut game_state, &mut dealer);
+    Some(game_state)
+}
+
+fn timed<T>(f: impl FnOnce() -> T) -> (T, Duration) {
+    let start = Instant::now();
+    let result = f();
+    (result, start.elapsed())
+}
+
+fn main() {
+    let usage = || -> ! {
+        eprintln!("usage: search_speedup [POSITIONS] [heuristic|mlp]");
+        std::process::exit(1);
+    };
+    let count = match std::env::args().nth(1).map(|arg| arg.parse::<u64>()) {
+        None => 20,
+        Some(Ok(count)) => count,
+        Some(Err(_)) => usage(),
+    };
+    let evaluator = match std::env::args().nth(2).as_deref() {
+        None | Some("heuristic") => Evaluator::Heuristic,
+        Some("mlp") => Evaluator::Mlp(Mlp::new(&[board_features(&GameState::new().board).len(), 64], 0)),
+        Some(_) => usage(),
+    };
+    let (mut reference_time, mut core_time) = (Duration::ZERO, Duration::ZERO);
+    let mut positions = 0;
+    for seed in 0..count {
+        let game_state = match position(seed) {
+            Some(game_state) => game_state,
+            None => continue,
+        };
+        let (expected, reference) = timed(|| reference_search(&game_state, MAX_SEARCH_DEPTH, &evaluator, Parallelism::Root));
+        let (actual, core) = timed(|| best_move(&game_state, MAX_SEARCH_DEPTH, &evaluator, Parallelism::Root));
+        match (expected, actual) {
+            (Ok(expected), Ok(actual)) => {
+                assert_eq!(
+                    (actual.score, actual.possible_move.pos),
+                    (expected.score, expected.possible_move.pos)
+                );
+            }
+            (expected, actual) => assert_eq!(expected.is_ok(), actual.is_ok()),
+        }
+        println!(
+            "position {:>3}: reference {:>9.2?}, search core {:>9.2?}",
+            seed, reference, core
+        );
+        reference_time += reference;
+        core_time += core;
+        positions += 1;
+    }
+
+    println!(
+        "{} positions: reference {:.2?}, search core {:.2?} - {:.1}x faster",
+        positions,
+        reference_time,
+        core_time,
+        reference_time.as_secs_f64() / core_time.as_secs_f64().max(1e-9)
+    );
+}
@@ -0,0 +1,37 @@
+#![no_main]
+
+// Parsers get input from users, files and clients - they should reject anything malformed
+// without panicking, and moves they accept should be legal.
+use libfuzzer_sys::fuzz_target;
+use roku_doku::brick::{brick_library, BrickId};
+use roku_doku::dataset::parse_positions;
+use roku_doku::game::{perform_action, GameState, Rules};
+use roku_doku::geometry::{parse_regions, Geometry};
+use roku_doku::level::parse_level;
+use roku_doku::mlp::Mlp;
+use roku_doku::notation::{parse_action, parse_position};
+
+fuzz_target!(|data: &[u8]| {
+    let text = match std::str::from_utf8(data) {
+        Ok(text) => text,
+        Err(_) => return,
+    };
+
+    let mut game_state = GameState::with_rules(Rules {
+        hold: true,
+        ..Rules::default()
+    });
+    game_state.available_bricks = brick_library().ids().take(3).collect();
+    if let Ok(action) = parse_action(text, &game_state) {
+        assert!(perform_action(&game_state, &action).is_ok());
+    }
+
+    if let Ok(id) = BrickId::parse(text) {
+        assert_eq!(id.to_string(), text);
+    }
+    let _ = parse_position(text, 16);
+    let _ = parse_level(text, Geometry::standard());
+    let _ = parse_regions(text, 9, 3);
+    let _ = parse_positions(text, Geometry::standard());
+    let _ = Mlp::parse(text);
+});
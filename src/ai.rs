@@ -0,0 +1,420 @@
+use crate::error::Error;
+use crate::game::{can_hold, get_possible_moves, hold_brick, is_dead, perform_move};
+use crate::game::{Action, GameState, Move, PossibleMove};
+use crate::log::Target;
+use crate::metrics::{self, Counter};
+use crate::mlp::{board_features, Mlp, POINTS_SCALE};
+use crate::notation::format_action;
+use crate::search::{best_move, score_move, Parallelism};
+use crate::{debug, Board, Occupancy};
+use rand::Rng;
+use rayon::prelude::*;
+
+#[derive(Clone, Debug)]
+pub struct PossibleMoveScore {
+    pub possible_move: PossibleMove,
+    pub score: i32,
+}
+
+impl From<PossibleMoveScore> for Move {
+    fn from(m: PossibleMoveScore) -> Self {
+        m.possible_move.into()
+    }
+}
+
+// Both are counted on the occupancy masks of the board, as they are computed for every leaf
+// of the search.
+fn count_filled_cells(occupancy: &Occupancy) -> i32 {
+    occupancy.iter().map(|row| row.count_ones() as i32).sum()
+}
+
+// 6 in line -> +1 point
+// 7 in line -> +2 points
+// 8 in line -> +3 points
+// (on a 9x9 board - in general it starts at 3 cells less than the full line)
+fn find_almost_full_lines(occupancy: &Occupancy, size: usize) -> i32 {
+    let rows = &occupancy[..size];
+    let columns = (0..size).map(|x| rows.iter().filter(|&&row| row >> x & 1 != 0).count());
+    rows.iter().map(|row| line_bonus(row.count_ones() as usize, size)).sum::<i32>()
+        + columns.map(|filled| line_bonus(filled, size)).sum::<i32>()
+}
+
+// a point for every filled cell, starting with the one 3 cells before the full line
+fn line_bonus(filled: usize, size: usize) -> i32 {
+    (filled + 1).saturating_sub(size.saturating_sub(3).max(1)) as i32
+}
+
+// The search doesn't go deeper than that, as it would take forever with bigger hands.
+pub const MAX_SEARCH_DEPTH: usize = 3;
+
+fn evaluate(board: &Board, points: i32) -> i32 {
+    evaluate_occupancy(&board.occupancy(), board.size() as usize, points)
+}
+
+pub(crate) fn evaluate_occupancy(occupancy: &Occupancy, size: usize, points: i32) -> i32 {
+    points - count_filled_cells(occupancy) + find_almost_full_lines(occupancy, size) * 2
+}
+
+// Scores positions at the leaves of the search.
+#[derive(Clone)]
+pub enum Evaluator {
+    // points, minus filled cells, plus bonus for almost full lines
+    Heuristic,
+    // points, plus points the network predicts until the end of the game
+    Mlp(Mlp),
+}
+
+impl Evaluator {
+    // A network has to be set up for boards of the given size - it's checked once here instead
+    // of on every evaluation.
+    pub fn check(&self, board: &Board) -> Result<(), Error> {
+        match self {
+            Evaluator::Heuristic => Ok(()),
+            Evaluator::Mlp(mlp) => mlp.check_input_size(board_features(board).len()),
+        }
+    }
+
+    fn evaluate(&self, game_state: &GameState) -> i32 {
+        self.evaluate_board(&game_state.board, game_state.points)
+    }
+
+    pub(crate) fn evaluate_board(&self, board: &Board, points: i32) -> i32 {
+        match self {
+            Evaluator::Heuristic => evaluate(board, points),
+            Evaluator::Mlp(mlp) => points + (mlp.forward(&board_features(board)) * POINTS_SCALE) as i32,
+        }
+    }
+
+    fn evaluate_batch(&self, game_states: &[&GameState]) -> Vec<i32> {
+        match self {
+            Evaluator::Heuristic => game_states.iter().map(|s| evaluate(&s.board, s.points)).collect(),
+            Evaluator::Mlp(mlp) => {
+                let features = game_states
+                    .iter()
+                    .map(|s| board_features(&s.board))
+                    .collect::<Vec<_>>();
+                mlp.forward_batch(&features)
+                    .iter()
+                    .zip(game_states)
+                    .map(|(prediction, s)| s.points + (prediction * POINTS_SCALE) as i32)
+                    .collect()
+            }
+        }
+    }
+}
+
+pub fn ai_submove(game_state: &GameState, m: &PossibleMove) -> Result<PossibleMoveScore, Error> {
+    ai_submove_with(game_state, m, &Evaluator::Heuristic, Parallelism::Root)
+}
+
+pub fn ai_submove_with(
+    game_state: &GameState,
+    m: &PossibleMove,
+    evaluator: &Evaluator,
+    parallelism: Parallelism,
+) -> Result<PossibleMoveScore, Error> {
+    let score = score_move(game_state, m, MAX_SEARCH_DEPTH, evaluator, parallelism)?;
+    Ok(PossibleMoveScore{possible_move: m.clone(), score})
+}
+
+// The original search, cloning the game state for every searched move. It's slow, but simple,
+// so it's kept as the reference for the search core in `search.rs` (for its tests and for
+// comparing the speed).
+
+// Positions at the leaves are collected, so they can be evaluated in batches.
+enum Submove {
+    Score(i32),
+    Leaf(Box<GameState>),
+}
+
+pub fn reference_submove_score(
+    game_state: &GameState,
+    m: &PossibleMove,
+    depth: usize,
+    evaluator: &Evaluator,
+    parallelism: Parallelism,
+) -> Result<i32, Error> {
+    Ok(match submove(game_state, m, depth, evaluator, parallelism)? {
+        Submove::Score(score) => score,
+        Submove::Leaf(s) => evaluator.evaluate(&s),
+    })
+}
+
+fn leaf(s: GameState, depth: usize) -> Submove {
+    metrics::inc(Counter::SearchLeaves);
+    metrics::add(Counter::SearchLeafDepth, (MAX_SEARCH_DEPTH + 1 - depth) as u64);
+    Submove::Leaf(Box::new(s))
+}
+
+// `parallelism` of the search after the move, deeper searches are always on a single thread
+fn submove(
+    game_state: &GameState,
+    m: &PossibleMove,
+    depth: usize,
+    evaluator: &Evaluator,
+    parallelism: Parallelism,
+) -> Result<Submove, Error> {
+    metrics::inc(Counter::SearchNodes);
+    let s = perform_move(game_state, &m.clone().into())?;
+    if s.available_bricks.is_empty(){
+        // the next hand is known in advance when playing with preview (at least partially)
+        if !s.upcoming_bricks.is_empty() {
+            let next_hand_size = std::cmp::min(s.rules.hand_size, s.upcoming_bricks.len());
+            let next_state = GameState {
+                available_bricks: s.upcoming_bricks[..next_hand_size].to_vec(),
+                ..s.clone()
+            };
+            if is_dead(&next_state) {
+                return Ok(Submove::Score(s.points - 1000));
+            }
+        }
+
+        return Ok(leaf(s, depth));
+    }
+
+    // dead positions are cut off before searching through all the ways of failing
+    if is_dead(&s) {
+        return Ok(Submove::Score(s.points - 1000));
+    }
+
+    if depth <= 1 {
+        return Ok(leaf(s, depth));
+    }
+
+    let best_sub_move = reference_search(&s, depth - 1, evaluator, parallelism)?;
+    Ok(Submove::Score(best_sub_move.score))
+}
+
+// tests avg score on 100 games:
+// 1) random move: 74
+// 2) min brick_index, then min x, then min y: 182
+// 3 )do move which gives the most points: 458
+// 4 )do 3 moves which gives the most points: 1858
+// 5) 4) + subtract 2 points for each filled cell: 1744
+// 6) 4) + subtract 1 points for each filled cell: 1807
+// 7) 4) : 1680
+// 8) 7) + bonus points for "almost full lines": 6089
+// Added missing bricks (brick_11 and brick)12) in this point
+// 8) 7) + bonus points for "almost full lines" * 2:
+pub fn ai_move(game_state: &GameState) -> Result<PossibleMoveScore, Error> {
+    ai_move_with(game_state, &Evaluator::Heuristic, Parallelism::Root)
+}
+
+pub fn ai_move_with(
+    game_state: &GameState,
+    evaluator: &Evaluator,
+    parallelism: Parallelism,
+) -> Result<PossibleMoveScore, Error> {
+    best_move(game_state, MAX_SEARCH_DEPTH, evaluator, parallelism)
+}
+
+pub fn reference_search(
+    game_state: &GameState,
+    depth: usize,
+    evaluator: &Evaluator,
+    parallelism: Parallelism,
+) -> Result<PossibleMoveScore, Error> {
+    let moves = get_possible_moves(game_state);
+    metrics::inc(Counter::Searches);
+    metrics::add(Counter::SearchBranches, moves.len() as u64);
+    // only the moves of this search are in parallel, like in the search core
+    let submove = |m: &PossibleMove| submove(game_state, m, depth, evaluator, Parallelism::Off);
+    let submoves = match parallelism {
+        Parallelism::Root => moves.par_iter().map(submove).collect::<Result<Vec<_>, _>>()?,
+        Parallelism::Off => moves.iter().map(submove).collect::<Result<Vec<_>, _>>()?,
+    };
+
+    let leaves = submoves
+        .iter()
+        .filter_map(|submove| match submove {
+            Submove::Leaf(s) => Some(s.as_ref()),
+            Submove::Score(_) => None,
+        })
+        .collect::<Vec<_>>();
+    let mut leaf_scores = evaluator.evaluate_batch(&leaves).into_iter();
+
+    moves
+        .into_iter()
+        .zip(submoves)
+        .map(|(m, submove)| PossibleMoveScore {
+            possible_move: m,
+            score: match submove {
+                Submove::Score(score) => score,
+                Submove::Leaf(_) => leaf_scores.next().unwrap(),
+            },
+        })
+        .max_by_key(|x| x.score)
+        .ok_or(Error::NoLegalMoves)
+}
+
+#[derive(Debug)]
+pub struct ActionScore {
+    pub action: Action,
+    pub score: i32,
+}
+
+impl From<PossibleMoveScore> for ActionScore {
+    fn from(m: PossibleMoveScore) -> Self {
+        ActionScore {
+            score: m.score,
+            action: Action::Place(m.into()),
+        }
+    }
+}
+
+// Like `ai_move`, but also considers putting a brick in the hold slot. Holding is checked
+// only for the current move, as checking it deeper in the search would make it way slower.
+pub fn ai_action(game_state: &GameState) -> Result<ActionScore, Error> {
+    ai_action_with(game_state, &Evaluator::Heuristic, Parallelism::Root)
+}
+
+pub fn ai_action_with(
+    game_state: &GameState,
+    evaluator: &Evaluator,
+    parallelism: Parallelism,
+) -> Result<ActionScore, Error> {
+    let holds = if can_hold(game_state) {
+        0..game_state.available_bricks.len() as i32
+    } else {
+        0..0
+    };
+
+    let best_hold = holds
+        .map(|brick_index| {
+            let s = hold_brick(game_state, brick_index)?;
+            let score = if s.available_bricks.is_empty() {
+                // parking the last brick brings a new hand, which isn't known yet
+                evaluator.evaluate(&s)
+            } else if get_possible_moves(&s).is_empty() {
+                return Ok(None);
+            } else {
+                best_move(&s, MAX_SEARCH_DEPTH, evaluator, parallelism)?.score
+            };
+
+            Ok(Some(ActionScore {
+                action: Action::Hold { brick_index },
+                score,
+            }))
+        })
+        .collect::<Result<Vec<_>, Error>>()?
+        .into_iter()
+        .flatten()
+        .max_by_key(|x| x.score);
+
+    if get_possible_moves(game_state).is_empty() {
+        return best_hold.ok_or(Error::NoLegalMoves);
+    }
+
+    let best_place: ActionScore = best_move(game_state, MAX_SEARCH_DEPTH, evaluator, parallelism)?.into();
+    let best = match best_hold {
+        Some(best_hold) if best_hold.score > best_place.score => best_hold,
+        _ => best_place,
+    };
+    debug!(
+        Target::Search,
+        "best action: {} - brick {} (score {})",
+        format_action(&best.action),
+        game_state.available_bricks[best.action.brick_index() as usize],
+        best.score
+    );
+    Ok(best)
+}
+
+// Takes the move scoring the most points right away (the first one if there are more).
+pub fn greedy_action(game_state: &GameState) -> Result<ActionScore, Error> {
+    let scores = get_possible_moves(game_state)
+        .into_iter()
+        .map(|m| {
+            let score = perform_move(game_state, &m.clone().into())?.points;
+            Ok(PossibleMoveScore{possible_move: m, score})
+        })
+        .collect::<Result<Vec<_>, Error>>()?;
+    let best = scores.into_iter().rev().max_by_key(|x| x.score).ok_or(Error::NoLegalMoves)?;
+    Ok(best.into())
+}
+
+pub fn random_action(game_state: &GameState) -> Result<ActionScore, Error> {
+    let moves = get_possible_moves(game_state);
+    if moves.is_empty() {
+        return Err(Error::NoLegalMoves);
+    }
+    let m = moves[rand::thread_rng().gen_range(0, moves.len())].clone();
+    let score = perform_move(game_state, &m.clone().into())?.points;
+    Ok(PossibleMoveScore{possible_move: m, score}.into())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::brick::{xy, BrickId};
+    use crate::game::Rules;
+    use crate::Cell;
+
+    #[test]
+    fn test_line_bonus() {
+        // 9x9 board
+        assert_eq!((0..=9).map(|filled| line_bonus(filled, 9)).collect::<Vec<_>>(), [0, 0, 0, 0, 0, 0, 1, 2, 3, 4]);
+        // every filled cell counts on small boards
+        assert_eq!(line_bonus(2, 3), 2);
+        assert_eq!(line_bonus(0, 1), 0);
+        assert_eq!(line_bonus(1, 4), 1);
+    }
+
+    #[test]
+    fn test_evaluate() {
+        let mut board = Board::default();
+        for x in 0..7 {
+            board.set(xy(x, 2), Cell::Filled);
+        }
+        board.set(xy(0, 0), Cell::Stone);
+        // 8 filled cells, a row with 7 of them (+2)
+        assert_eq!(evaluate(&board, 100), 100 - 8 + 2 * 2);
+    }
+
+    #[test]
+    fn test_no_legal_moves() {
+        let mut game_state = GameState::new();
+        game_state.available_bricks = vec![BrickId::new(1, 0).unwrap()];
+        for y in 0..9 {
+            for x in 0..9 {
+                if (x + y) % 2 == 0 {
+                    game_state.board.set(xy(x, y), Cell::Filled);
+                }
+            }
+        }
+
+        assert_eq!(ai_move(&game_state).err(), Some(Error::NoLegalMoves));
+        assert_eq!(ai_action(&game_state).err(), Some(Error::NoLegalMoves));
+        assert_eq!(greedy_action(&game_state).err(), Some(Error::NoLegalMoves));
+        assert_eq!(random_action(&game_state).err(), Some(Error::NoLegalMoves));
+    }
+
+    #[test]
+    fn test_hold_only_at_the_root() {
+        // only single cells fit on the board, the 2-cell line doesn't
+        let mut game_state = GameState::with_rules(Rules {
+            hold: true,
+            ..Rules::default()
+        });
+        game_state.available_bricks = vec![BrickId::new(1, 0).unwrap(), BrickId::new(0, 0).unwrap()];
+        for y in 0..9 {
+            for x in 0..9 {
+                if (x + y) % 2 == 0 {
+                    game_state.board.set(xy(x, y), Cell::Filled);
+                }
+            }
+        }
+
+        // holding the line now saves the game
+        let best = ai_action(&game_state).unwrap();
+        assert!(matches!(best.action, Action::Hold { brick_index: 0 }));
+        assert!(best.score > game_state.points - 1000);
+
+        // Holding the line after putting the cell would save it too, but the search doesn't hold
+        // below the root, so putting the cell is scored as a dead end.
+        game_state.hold_used = true;
+        let best = ai_action(&game_state).unwrap();
+        assert!(matches!(best.action, Action::Place(_)));
+        assert!(best.score < game_state.points - 900);
+    }
+}
@@ -0,0 +1,136 @@
+use crate::brick::{xy, Brick, XY};
+use crate::error::Error;
+use crate::{Board, Cell};
+use crate::metrics::{self, Counter};
+
+pub fn print_board(board: &Board) {
+    print_board_with_highlight(board, &[]);
+}
+
+// highlighted cells are displayed as `o` - e.g. to show where a hinted brick would go
+pub fn print_board_with_highlight(board: &Board, highlighted: &[XY]) {
+    let size = board.size() as usize;
+    // a board without boxes is displayed as a single big box
+    let box_size = if board.geometry().box_size > 0 {
+        board.geometry().box_size as usize
+    } else {
+        size
+    };
+    let label_width = size.to_string().len();
+
+    let letters = (0..size)
+        .map(|x| (b'a' + x as u8) as char)
+        .collect::<Vec<_>>()
+        .chunks(box_size)
+        .map(|chunk| chunk.iter().collect::<String>())
+        .collect::<Vec<_>>()
+        .join(" ");
+    let header = format!("{} {}", " ".repeat(label_width), letters);
+    let separator = format!("{}{}", " ".repeat(label_width), "-".repeat(size + size / box_size + 1));
+
+    println!("{}", header);
+    for (row_counter, row) in board.rows().enumerate() {
+        if row_counter % box_size == 0 {
+            println!("{}", separator);
+        }
+
+        print!("{:>width$}", row_counter + 1, width = label_width);
+        for (col_counter, cell) in row.iter().enumerate() {
+            if col_counter % box_size == 0 {
+                print!("|");
+            }
+            let is_highlighted = highlighted
+                .iter()
+                .any(|v| v.x == col_counter as i32 && v.y == row_counter as i32);
+            let c = if is_highlighted { 'o' } else { cell.symbol() };
+            print!("{}", c);
+        }
+        println!("|{}", row_counter + 1);
+    }
+    println!("{}", separator);
+    println!("{}", header);
+}
+
+// Positions and cells out of the board (including negative ones) are just not free.
+pub fn can_put_brick(board: &Board, brick: &Brick, pos: &XY) -> bool {
+    metrics::inc(Counter::CanPutBrick);
+
+    let size = board.size();
+    if pos.x < 0 || pos.y < 0 || pos.x + brick.width() > size || pos.y + brick.height() > size {
+        return false;
+    }
+    brick
+        .offsets()
+        .iter()
+        .all(|offset| !board.get(xy(pos.x + offset.x, pos.y + offset.y)).is_filled())
+}
+
+pub fn put_brick(board: &Board, brick: &Brick, pos: &XY) -> Result<Board, Error> {
+    if !can_put_brick(board, brick, pos) {
+        return Err(Error::IllegalMove(format!(
+            "the brick doesn't fit at {},{} - it's off the board or covers a filled cell",
+            pos.x, pos.y
+        )));
+    }
+    metrics::inc(Counter::PutBrick);
+
+    let mut new_board = *board;
+    for offset in brick.offsets() {
+        new_board.set(xy(pos.x + offset.x, pos.y + offset.y), Cell::Filled);
+    }
+
+    Ok(new_board)
+}
+
+pub fn possible_moves(board: &Board, brick: &Brick) -> Vec<XY> {
+    metrics::inc(Counter::PossibleMoves);
+
+    // only the positions where the brick is on the board, each checked with a few mask tests
+    let occupancy = board.occupancy();
+    brick
+        .anchors(board.size())
+        .filter(|&pos| brick.fits(&occupancy, pos))
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing;
+    use proptest::prelude::*;
+
+    fn filled_cells(board: &Board) -> usize {
+        board.rows().flatten().filter(|cell| cell.is_filled()).count()
+    }
+
+    proptest! {
+        #[test]
+        fn test_put_brick_fills_only_the_brick_cells(
+            (board, pos) in testing::board().prop_flat_map(|board| (Just(board), testing::position(board.size()))),
+            brick in testing::library_brick(),
+        ) {
+            match put_brick(&board, &brick, &pos) {
+                Ok(new_board) => {
+                    prop_assert!(can_put_brick(&board, &brick, &pos));
+                    prop_assert_eq!(filled_cells(&new_board), filled_cells(&board) + brick.offsets().len());
+                    for v in brick.offsets() {
+                        prop_assert_eq!(new_board.get(xy(pos.x + v.x, pos.y + v.y)), Cell::Filled);
+                    }
+                }
+                Err(_) => prop_assert!(!can_put_brick(&board, &brick, &pos)),
+            }
+        }
+
+        #[test]
+        fn test_possible_moves_are_the_free_positions(board in testing::board(), brick in testing::library_brick()) {
+            let moves = possible_moves(&board, &brick);
+            let size = board.size();
+            for y in -2..size + 2 {
+                for x in -2..size + 2 {
+                    let pos = xy(x, y);
+                    prop_assert_eq!(moves.contains(&pos), can_put_brick(&board, &brick, &pos));
+                }
+            }
+        }
+    }
+}
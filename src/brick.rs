@@ -1,7 +1,11 @@
+use crate::error::Error;
+use crate::geometry::MAX_BOARD_SIZE;
+use crate::Occupancy;
 use lazy_static::lazy_static;
 use rand::Rng;
+use std::fmt;
 
-#[derive(Copy, Clone, Debug)]
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Hash)]
 pub struct XY {
     pub x: i32,
     pub y: i32,
@@ -13,226 +17,321 @@ pub fn xy(x: i32, y: i32) -> XY {
 
 // Offsets are assumed to be >= 0.
 // Min offset must be = 0 for both x and y.
-#[derive(Clone, Debug)]
+// Besides the offsets, a brick keeps what the move generation needs, computed once: its bounding
+// box and its cells as bit masks of rows (bit x of `rows[y]` is the offset x,y). The cells the
+// brick covers at a position are then just the masks shifted by the position.
+#[derive(Clone, Debug, PartialEq)]
 pub struct Brick {
-    pub offsets: Vec<XY>,
+    offsets: Vec<XY>,
+    width: i32,
+    height: i32,
+    rows: Occupancy,
 }
 
 fn normalize_brick(brick: &Brick) -> Brick {
     let min_x = brick.offsets.iter().min_by_key(|v| v.x).unwrap().x;
     let min_y = brick.offsets.iter().min_by_key(|v| v.y).unwrap().y;
 
-    Brick {
-        offsets: brick
-            .offsets
-            .iter()
-            .map(|v| xy(v.x - min_x, v.y - min_y))
-            .collect(),
+    Brick::from_offsets(brick.offsets.iter().map(|v| xy(v.x - min_x, v.y - min_y)).collect())
+}
+
+impl Brick {
+    // checked constructor for bricks coming from outside (the library bricks are well-formed)
+    pub fn new(offsets: Vec<XY>) -> Result<Brick, Error> {
+        check_offsets(&offsets)?;
+        Ok(Brick::from_offsets(offsets))
+    }
+
+    // the offsets have to be checked already
+    fn from_offsets(offsets: Vec<XY>) -> Brick {
+        let mut rows = Occupancy::default();
+        for v in &offsets {
+            rows[v.y as usize] |= 1 << v.x;
+        }
+        Brick {
+            width: offsets.iter().map(|v| v.x + 1).max().unwrap_or(0),
+            height: offsets.iter().map(|v| v.y + 1).max().unwrap_or(0),
+            offsets,
+            rows,
+        }
+    }
+
+    pub fn offsets(&self) -> &[XY] {
+        &self.offsets
+    }
+
+    // size of the bounding box
+    pub fn width(&self) -> i32 {
+        self.width
+    }
+
+    pub fn height(&self) -> i32 {
+        self.height
+    }
+
+    // positions where the bounding box is on a board of the given size, column by column -
+    // the only ones where the brick can be put
+    pub fn anchors(&self, board_size: i32) -> impl Iterator<Item = XY> {
+        let (width, height) = (self.width, self.height);
+        (0..=board_size - width).flat_map(move |x| (0..=board_size - height).map(move |y| xy(x, y)))
+    }
+
+    // whether the brick put at an anchor covers only cells which are free in `occupancy`
+    #[inline]
+    pub fn fits(&self, occupancy: &Occupancy, pos: XY) -> bool {
+        let rows = &self.rows[..self.height as usize];
+        rows.iter()
+            .zip(&occupancy[pos.y as usize..])
+            .all(|(row, filled)| (row << pos.x) & filled == 0)
+    }
+
+    // marks the cells of the brick put at an anchor as filled
+    #[inline]
+    pub fn fill(&self, occupancy: &mut Occupancy, pos: XY) {
+        let rows = &self.rows[..self.height as usize];
+        for (row, filled) in rows.iter().zip(&mut occupancy[pos.y as usize..]) {
+            *filled |= row << pos.x;
+        }
+    }
+}
+
+fn check_offsets(offsets: &[XY]) -> Result<(), Error> {
+    let min_x = offsets.iter().map(|v| v.x).min();
+    let min_y = offsets.iter().map(|v| v.y).min();
+    match (min_x, min_y) {
+        (None, _) | (_, None) => return Err(Error::MalformedBrick("a brick needs at least one cell".to_string())),
+        (Some(0), Some(0)) => {}
+        _ => {
+            return Err(Error::MalformedBrick(
+                "the smallest offsets of a brick should be 0 for both x and y".to_string(),
+            ))
+        }
+    }
+    if offsets.iter().any(|v| v.x >= MAX_BOARD_SIZE || v.y >= MAX_BOARD_SIZE) {
+        return Err(Error::MalformedBrick(format!(
+            "a brick should fit in {0}x{0} cells, like the biggest board",
+            MAX_BOARD_SIZE
+        )));
+    }
+    for (i, v) in offsets.iter().enumerate() {
+        if offsets[..i].contains(v) {
+            return Err(Error::MalformedBrick(format!("the cell {},{} is repeated", v.x, v.y)));
+        }
     }
+    Ok(())
 }
 
-fn rotate_clockwise(brick: &Brick) -> Brick {
-    let max_x = brick.offsets.iter().max_by_key(|v| v.x).unwrap().x;
-    let max_y = brick.offsets.iter().max_by_key(|v| v.y).unwrap().y;
+fn rotate_clockwise(brick: &Brick) -> Result<Brick, Error> {
+    check_offsets(&brick.offsets)?;
+    let max_x = brick.offsets.iter().map(|v| v.x).max().unwrap_or(0);
+    let max_y = brick.offsets.iter().map(|v| v.y).max().unwrap_or(0);
     let max = std::cmp::max(max_x, max_y);
 
-    if max == 0 {
-        return brick.clone();
-    }
-
-    if max == 1 {
-        return normalize_brick(&Brick {
-            offsets: brick
-                .offsets
-                .iter()
-                .map(|v| {
-                    if v.x == 0 && v.y == 0 {
-                        xy(1, 0)
-                    } else if v.x == 1 && v.y == 0 {
-                        xy(1, 1)
-                    } else if v.x == 1 && v.y == 1 {
-                        xy(0, 1)
-                    } else if v.x == 0 && v.y == 1 {
-                        xy(0, 0)
-                    } else {
-                        panic!()
-                    }
-                })
-                .collect(),
-        });
-    }
-
-    if max == 2 {
-        return normalize_brick(&Brick {
-            offsets: brick
-                .offsets
-                .iter()
-                .map(|v| {
-                    if v.x == 0 && v.y == 0 {
-                        xy(2, 0)
-                    } else if v.x == 2 && v.y == 0 {
-                        xy(2, 2)
-                    } else if v.x == 2 && v.y == 2 {
-                        xy(0, 2)
-                    } else if v.x == 0 && v.y == 2 {
-                        xy(0, 0)
-                    } else if v.x == 1 && v.y == 0 {
-                        xy(2, 1)
-                    } else if v.x == 2 && v.y == 1 {
-                        xy(1, 2)
-                    } else if v.x == 1 && v.y == 2 {
-                        xy(0, 1)
-                    } else if v.x == 0 && v.y == 1 {
-                        xy(1, 0)
-                    } else if v.x == 1 && v.y == 1 {
-                        xy(1, 1)
-                    } else {
-                        panic!()
-                    }
-                })
-                .collect(),
-        });
-    }
-
-    // long lines are handled in a special way, as there are only 2 bricks not fitting in 3x3 box
-    if (max_x == 4 && max_y == 0) || (max_y == 4 && max_x == 0) ||
-        (max_x == 3 && max_y == 0) || (max_y == 3 && max_x == 0)
-    {
-        return Brick {
-            offsets: brick.offsets.iter().map(|v| xy(v.y, v.x)).collect(),
-        };
-    }
-
-    panic!("unsupported brick: {:#?}", brick);
+    // long lines are just transposed, as there are only 2 bricks not fitting in 3x3 box
+    if max > 2 && (max_x == 0 || max_y == 0) {
+        return Ok(Brick::from_offsets(brick.offsets.iter().map(|v| xy(v.y, v.x)).collect()));
+    }
+
+    // rotating around the center of the bounding square
+    Ok(normalize_brick(&Brick::from_offsets(
+        brick.offsets.iter().map(|v| xy(max - v.y, v.x)).collect(),
+    )))
 }
 
-fn all_brick_rotations(brick: &Brick) -> Vec<Brick> {
+fn all_brick_rotations(brick: &Brick) -> Result<Vec<Brick>, Error> {
     let b1 = brick.clone();
-    let b2 = rotate_clockwise(&b1);
-    let b3 = rotate_clockwise(&b2);
-    let b4 = rotate_clockwise(&b3);
+    let b2 = rotate_clockwise(&b1)?;
+    let b3 = rotate_clockwise(&b2)?;
+    let b4 = rotate_clockwise(&b3)?;
 
-    vec![b1, b2, b3, b4]
+    Ok(vec![b1, b2, b3, b4])
 }
 
+// number of the brick shapes in the library - every one of them comes in 4 rotations
+pub const SHAPES: usize = 13;
+pub const ROTATIONS: usize = 4;
+
+// A library brick: its shape turned clockwise `rotation` times. Hands and moves keep these
+// instead of the bricks, which are looked up in the library when needed. Written as e.g. `7r1`.
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub struct BrickId(u8);
+
+impl BrickId {
+    pub fn new(shape: usize, rotation: usize) -> Result<BrickId, Error> {
+        if shape >= SHAPES || rotation >= ROTATIONS {
+            return Err(Error::MalformedBrick(format!(
+                "there is no brick {}r{} - the library has {} shapes in {} rotations",
+                shape, rotation, SHAPES, ROTATIONS
+            )));
+        }
+        Ok(BrickId((shape * ROTATIONS + rotation) as u8))
+    }
+
+    pub fn parse(text: &str) -> Result<BrickId, Error> {
+        let invalid = || Error::Parse(format!("invalid brick: `{}` (expected e.g. `7r1`)", text));
+        let (shape, rotation) = text.split_once('r').ok_or_else(invalid)?;
+        let shape = shape.parse().map_err(|_| invalid())?;
+        let rotation = rotation.parse().map_err(|_| invalid())?;
+        let id = BrickId::new(shape, rotation)?;
+        // no signs or leading zeros - every brick is written in a single way
+        if id.to_string() != text {
+            return Err(invalid());
+        }
+        Ok(id)
+    }
+
+    pub fn shape(self) -> usize {
+        self.0 as usize / ROTATIONS
+    }
+
+    pub fn rotation(self) -> usize {
+        self.0 as usize % ROTATIONS
+    }
+
+    // position in the library
+    pub fn index(self) -> usize {
+        self.0 as usize
+    }
+
+    pub fn brick(self) -> &'static Brick {
+        &LIBRARY.bricks[self.index()]
+    }
+}
+
+impl fmt::Display for BrickId {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}r{}", self.shape(), self.rotation())
+    }
+}
+
+// the shapes in order, each followed by its rotations
 fn generate_brick_library() -> Vec<Brick> {
     // X
-    let brick_0 = Brick {
-        offsets: vec![xy(0, 0)],
-    };
+    let brick_0 = Brick::from_offsets(vec![xy(0, 0)]);
 
     // XX
-    let brick_1 = Brick {
-        offsets: vec![xy(0, 0), xy(1, 0)],
-    };
+    let brick_1 = Brick::from_offsets(vec![xy(0, 0), xy(1, 0)]);
 
     // XXX
-    let brick_2 = Brick {
-        offsets: vec![xy(0, 0), xy(1, 0), xy(2, 0)],
-    };
+    let brick_2 = Brick::from_offsets(vec![xy(0, 0), xy(1, 0), xy(2, 0)]);
 
     // XXXX
-    let brick_3 = Brick {
-        offsets: vec![xy(0, 0), xy(1, 0), xy(2, 0), xy(3, 0)],
-    };
+    let brick_3 = Brick::from_offsets(vec![xy(0, 0), xy(1, 0), xy(2, 0), xy(3, 0)]);
 
     // XXXXX
-    let brick_4 = Brick {
-        offsets: vec![xy(0, 0), xy(1, 0), xy(2, 0), xy(3, 0), xy(4, 0)],
-    };
+    let brick_4 = Brick::from_offsets(vec![xy(0, 0), xy(1, 0), xy(2, 0), xy(3, 0), xy(4, 0)]);
 
     // XX
     // X
-    let brick_5 = Brick {
-        offsets: vec![xy(0, 0), xy(1, 0), xy(0, 1)],
-    };
+    let brick_5 = Brick::from_offsets(vec![xy(0, 0), xy(1, 0), xy(0, 1)]);
 
     // XXX
     //  X
-    let brick_6 = Brick {
-        offsets: vec![xy(0, 0), xy(1, 0), xy(2, 0), xy(1, 1)],
-    };
+    let brick_6 = Brick::from_offsets(vec![xy(0, 0), xy(1, 0), xy(2, 0), xy(1, 1)]);
 
     // XXX
     //  X
     //  X
-    let brick_7 = Brick {
-        offsets: vec![xy(0, 0), xy(1, 0), xy(2, 0), xy(1, 1), xy(1, 2)],
-    };
+    let brick_7 = Brick::from_offsets(vec![xy(0, 0), xy(1, 0), xy(2, 0), xy(1, 1), xy(1, 2)]);
 
     // XXX
     // X X
-    let brick_8 = Brick {
-        offsets: vec![xy(0, 0), xy(1, 0), xy(2, 0), xy(0, 1), xy(2, 1)],
-    };
+    let brick_8 = Brick::from_offsets(vec![xy(0, 0), xy(1, 0), xy(2, 0), xy(0, 1), xy(2, 1)]);
 
     // XXX
     // X X
     // X X
-    let brick_9 = Brick {
-        offsets: vec![
-            xy(0, 0),
-            xy(1, 0),
-            xy(2, 0),
-            xy(0, 1),
-            xy(2, 1),
-            xy(0, 2),
-            xy(2, 2),
-        ],
-    };
+    let brick_9 = Brick::from_offsets(vec![
+        xy(0, 0),
+        xy(1, 0),
+        xy(2, 0),
+        xy(0, 1),
+        xy(2, 1),
+        xy(0, 2),
+        xy(2, 2),
+    ]);
 
     // XX
     //  XX
-    let brick_10 = Brick {
-        offsets: vec![xy(0, 0), xy(1, 0), xy(1, 1), xy(2, 1)],
-    };
+    let brick_10 = Brick::from_offsets(vec![xy(0, 0), xy(1, 0), xy(1, 1), xy(2, 1)]);
 
     // X
     //  X
-    let brick_11 = Brick {
-        offsets: vec![xy(0, 0), xy(1, 1)],
-    };
+    let brick_11 = Brick::from_offsets(vec![xy(0, 0), xy(1, 1)]);
 
     // X
     //  X
     //   X
-    let brick_12 = Brick {
-        offsets: vec![xy(0, 0), xy(1, 1), xy(2, 2)],
-    };
+    let brick_12 = Brick::from_offsets(vec![xy(0, 0), xy(1, 1), xy(2, 2)]);
+
+    let shapes: [Brick; SHAPES] = [
+        brick_0, brick_1, brick_2, brick_3, brick_4, brick_5, brick_6, brick_7, brick_8, brick_9, brick_10,
+        brick_11, brick_12,
+    ];
+    shapes
+        .iter()
+        .flat_map(|brick| all_brick_rotations(brick).expect("the library bricks are well-formed"))
+        .collect()
+}
 
-    let mut ret = vec![];
-    ret.extend(all_brick_rotations(&brick_0));
-    ret.extend(all_brick_rotations(&brick_1));
-    ret.extend(all_brick_rotations(&brick_2));
-    ret.extend(all_brick_rotations(&brick_3));
-    ret.extend(all_brick_rotations(&brick_4));
-    ret.extend(all_brick_rotations(&brick_5));
-    ret.extend(all_brick_rotations(&brick_6));
-    ret.extend(all_brick_rotations(&brick_7));
-    ret.extend(all_brick_rotations(&brick_8));
-    ret.extend(all_brick_rotations(&brick_9));
-    ret.extend(all_brick_rotations(&brick_10));
-    ret.extend(all_brick_rotations(&brick_11));
-    ret.extend(all_brick_rotations(&brick_12));
-
-    ret
+// All the bricks that can be dealt, in all rotations, indexed by `BrickId`. Built once, with the
+// placement data of every brick precomputed.
+pub struct BrickLibrary {
+    bricks: Vec<Brick>,
 }
 
-pub fn random_brick() -> Brick {
-    lazy_static! {
-        static ref LIBRARY: Vec<Brick> = generate_brick_library();
+impl BrickLibrary {
+    pub fn bricks(&self) -> &[Brick] {
+        &self.bricks
     }
-    let mut rng = rand::thread_rng();
-    LIBRARY[rng.gen_range(0, LIBRARY.len())].clone()
+
+    pub fn ids(&self) -> impl Iterator<Item = BrickId> {
+        (0..self.bricks.len()).map(|i| BrickId(i as u8))
+    }
+
+    pub fn len(&self) -> usize {
+        self.bricks.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.bricks.is_empty()
+    }
+
+    pub fn random<R: Rng>(&self, rng: &mut R) -> BrickId {
+        BrickId(rng.gen_range(0, self.bricks.len()) as u8)
+    }
+}
+
+lazy_static! {
+    static ref LIBRARY: BrickLibrary = BrickLibrary {
+        bricks: generate_brick_library(),
+    };
+}
+
+pub fn brick_library() -> &'static BrickLibrary {
+    &LIBRARY
+}
+
+pub fn random_brick() -> BrickId {
+    random_brick_from(&mut rand::thread_rng())
+}
+
+pub fn random_brick_from<R: Rng>(rng: &mut R) -> BrickId {
+    LIBRARY.random(rng)
 }
 
 pub fn print_brick(brick: &Brick) {
-    let max_x = brick.offsets.iter().max_by_key(|v|v.x).unwrap().x;
-    let max_y = brick.offsets.iter().max_by_key(|v|v.y).unwrap().y;
+    print_cells(&brick.offsets);
+}
+
+// cells with offsets >= 0, e.g. a few bricks next to each other
+pub fn print_cells(cells: &[XY]) {
+    let max_x = cells.iter().max_by_key(|v|v.x).unwrap().x;
+    let max_y = cells.iter().max_by_key(|v|v.y).unwrap().y;
 
     for y in 0..max_y+1 {
         for x in 0..max_x+1 {
-            let c = if brick.offsets.iter().find(|v| v.x == x && v.y == y).is_some(){
+            let c = if cells.iter().find(|v| v.x == x && v.y == y).is_some(){
                 'X'
             } else {' '};
 
@@ -243,15 +342,103 @@ pub fn print_brick(brick: &Brick) {
 }
 
 pub fn print_all_bricks() {
-    let bricks = generate_brick_library();
-
-    println!("printing {} bricks from the library", bricks.len());
-    let mut i = 0;
-    for brick in bricks {
-        println!("brick {}:", &i);
-        i = i+1;
+    println!("printing {} bricks from the library", LIBRARY.len());
+    for id in LIBRARY.ids() {
+        println!("brick {}:", id);
 
-        print_brick(&brick);
+        print_brick(id.brick());
         println!("----------------");
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing;
+    use proptest::prelude::*;
+
+    #[test]
+    fn test_brick_ids() {
+        assert_eq!(LIBRARY.len(), SHAPES * ROTATIONS);
+        for id in LIBRARY.ids() {
+            assert_eq!(BrickId::new(id.shape(), id.rotation()), Ok(id));
+            assert_eq!(BrickId::parse(&id.to_string()), Ok(id));
+            // rotations of a shape follow it in the library
+            let turned = (0..id.rotation()).fold(BrickId::new(id.shape(), 0).unwrap().brick().clone(), |brick, _| {
+                rotate_clockwise(&brick).unwrap()
+            });
+            assert_eq!(&turned, id.brick());
+        }
+        assert_eq!(BrickId::new(7, 1).unwrap().to_string(), "7r1");
+
+        assert!(BrickId::new(SHAPES, 0).is_err());
+        assert!(BrickId::new(0, ROTATIONS).is_err());
+        for text in ["", "7", "r1", "7r", "x7r1", "13r0", "7r-1", "07r1", "+7r1"] {
+            assert!(BrickId::parse(text).is_err(), "{}", text);
+        }
+    }
+
+    #[test]
+    fn test_four_rotations_make_a_full_turn() {
+        for brick in brick_library().bricks() {
+            let mut rotated = brick.clone();
+            for _ in 0..4 {
+                rotated = rotate_clockwise(&rotated).unwrap();
+            }
+            assert_eq!(sorted_cells(&rotated), sorted_cells(brick));
+        }
+    }
+
+    #[test]
+    fn test_malformed_bricks() {
+        assert!(Brick::new(vec![xy(0, 0), xy(1, 0)]).is_ok());
+        for offsets in [vec![], vec![xy(1, 0)], vec![xy(0, -1), xy(0, 0)], vec![xy(0, 0), xy(0, 0)]] {
+            assert!(matches!(Brick::new(offsets), Err(Error::MalformedBrick(_))));
+        }
+        assert!(rotate_clockwise(&Brick::from_offsets(vec![])).is_err());
+        assert!(Brick::new(vec![xy(0, 0), xy(MAX_BOARD_SIZE, 0)]).is_err());
+    }
+
+    #[test]
+    fn test_placement_data() {
+        // XXX
+        //  X
+        let brick = Brick::new(vec![xy(0, 0), xy(1, 0), xy(2, 0), xy(1, 1)]).unwrap();
+        assert_eq!((brick.width(), brick.height()), (3, 2));
+        assert_eq!(&brick.rows[..3], &[0b111, 0b010, 0]);
+
+        let anchors = brick.anchors(9).collect::<Vec<_>>();
+        assert_eq!(anchors.len(), 7 * 8);
+        assert_eq!(anchors[..2], [xy(0, 0), xy(0, 1)]);
+        assert_eq!(anchors.last(), Some(&xy(6, 7)));
+        assert_eq!(brick.anchors(2).count(), 0);
+
+        let mut occupancy = Occupancy::default();
+        occupancy[4] = 1 << 5;
+        assert!(!brick.fits(&occupancy, xy(4, 3)));
+        assert!(!brick.fits(&occupancy, xy(3, 4)));
+        assert!(brick.fits(&occupancy, xy(3, 3)));
+        assert!(brick.fits(&occupancy, xy(5, 2)));
+    }
+
+    fn sorted_cells(brick: &Brick) -> Vec<(i32, i32)> {
+        let mut cells = brick.offsets.iter().map(|v| (v.x, v.y)).collect::<Vec<_>>();
+        cells.sort_unstable();
+        cells
+    }
+
+    proptest! {
+        #[test]
+        fn test_rotations_of_any_shape(brick in testing::brick()) {
+            let mut rotated = brick.clone();
+            for turn in 1..=4 {
+                rotated = rotate_clockwise(&rotated).unwrap();
+                prop_assert!(Brick::new(rotated.offsets.clone()).is_ok());
+                prop_assert_eq!(rotated.offsets.len(), brick.offsets.len());
+                if turn == 4 {
+                    prop_assert_eq!(sorted_cells(&rotated), sorted_cells(&brick));
+                }
+            }
+        }
+    }
+}
@@ -0,0 +1,156 @@
+use crate::error::Error;
+use crate::game::{Action, GameState};
+use crate::geometry::Geometry;
+use crate::level::parse_level;
+use crate::notation::{format_action, format_bricks};
+use crate::Board;
+use std::io::{self, Write};
+
+// A position from a self-play game with the action chosen by the bot and its search score.
+pub struct Sample {
+    pub game_state: GameState,
+    pub action: Action,
+    pub score: i32,
+}
+
+// Samples are written as CSV, one row per move:
+// - board - rows of cells (`.` empty, `X` filled, `#` stone, `@` double stone) separated with `/`,
+// - hand, next, held - bricks as their IDs (e.g. `7r1`, shape and rotation) separated with spaces,
+// - action - in the standard notation (e.g. `3 d4` or `hold 2`),
+// - final_points - the result of the whole game, known once it's over.
+pub const CSV_HEADER: &str = "game,move,board,hand,next,held,points,action,score,final_points";
+
+pub fn write_header(out: &mut impl Write) -> io::Result<()> {
+    writeln!(out, "{}", CSV_HEADER)
+}
+
+pub fn write_game(out: &mut impl Write, game: usize, samples: &[Sample], final_points: i32) -> io::Result<()> {
+    for (move_number, sample) in samples.iter().enumerate() {
+        let game_state = &sample.game_state;
+        let board = game_state
+            .board
+            .rows()
+            .map(|row| row.iter().map(|cell| cell.symbol()).collect::<String>())
+            .collect::<Vec<_>>()
+            .join("/");
+
+        writeln!(
+            out,
+            "{},{},{},\"{}\",\"{}\",\"{}\",{},{},{},{}",
+            game,
+            move_number,
+            board,
+            format_bricks(&game_state.available_bricks),
+            format_bricks(&game_state.upcoming_bricks),
+            format_bricks(game_state.held_brick.as_slice()),
+            game_state.points,
+            format_action(&sample.action),
+            sample.score,
+            final_points
+        )?;
+    }
+    Ok(())
+}
+
+// Board of a sample with the points scored before and after it, for training evaluators.
+pub struct Position {
+    pub board: Board,
+    pub points: i32,
+    pub final_points: i32,
+}
+
+// fields with commas are quoted
+fn split_csv_line(line: &str) -> Vec<&str> {
+    let mut fields = vec![];
+    let mut start = 0;
+    let mut quoted = false;
+    for (i, c) in line.char_indices() {
+        match c {
+            '"' => quoted = !quoted,
+            ',' if !quoted => {
+                fields.push(line[start..i].trim_matches('"'));
+                start = i + 1;
+            }
+            _ => {}
+        }
+    }
+    fields.push(line[start..].trim_matches('"'));
+    fields
+}
+
+pub fn parse_positions(text: &str, geometry: &'static Geometry) -> Result<Vec<Position>, Error> {
+    let mut lines = text.lines().enumerate();
+    match lines.next() {
+        Some((_, header)) if header == CSV_HEADER => {}
+        _ => return Err(Error::Parse(format!("the data should start with `{}`", CSV_HEADER))),
+    }
+
+    let mut positions = vec![];
+    for (line_number, line) in lines {
+        let error = |e: String| Error::Parse(format!("line {}: {}", line_number + 1, e));
+        let fields = split_csv_line(line);
+        if fields.len() != 10 {
+            return Err(error(format!("expected 10 fields, found {}", fields.len())));
+        }
+        let number = |field: &str| field.parse::<i32>().map_err(|_| error(format!("invalid number: {}", field)));
+
+        positions.push(Position {
+            board: parse_level(&fields[2].replace('/', "\n"), geometry).map_err(|e| error(e.to_string()))?,
+            points: number(fields[6])?,
+            final_points: number(fields[9])?,
+        });
+    }
+    Ok(positions)
+}
+
+pub fn load_positions(path: &str, geometry: &'static Geometry) -> Result<Vec<Position>, Error> {
+    let text = std::fs::read_to_string(path)
+        .map_err(|e| Error::Io(format!("can't read data file {}: {}", path, e)))?;
+    parse_positions(&text, geometry)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::brick::{xy, BrickId};
+    use crate::game::Move;
+
+    #[test]
+    fn test_write_game() {
+        let mut game_state = GameState::new();
+        game_state.available_bricks = vec![BrickId::new(1, 0).unwrap(), BrickId::new(0, 2).unwrap()];
+        let samples = vec![Sample {
+            game_state,
+            action: Action::Place(Move {
+                brick_index: 1,
+                pos: xy(2, 0),
+            }),
+            score: 40,
+        }];
+
+        let mut out = vec![];
+        write_header(&mut out).unwrap();
+        write_game(&mut out, 3, &samples, 120).unwrap();
+        let text = String::from_utf8(out).unwrap();
+        let lines = text.lines().collect::<Vec<_>>();
+        assert_eq!(lines.len(), 2);
+        assert_eq!(lines[0], CSV_HEADER);
+        assert_eq!(
+            lines[1],
+            format!("3,0,{},\"1r0 0r2\",\"\",\"\",0,2 c1,40,120", ["........."; 9].join("/"))
+        );
+
+        let positions = parse_positions(&text, Geometry::standard()).unwrap();
+        assert_eq!(positions.len(), 1);
+        assert_eq!(positions[0].board, Board::default());
+        assert_eq!(positions[0].points, 0);
+        assert_eq!(positions[0].final_points, 120);
+    }
+
+    #[test]
+    fn test_parse_invalid_positions() {
+        let geometry = Geometry::standard();
+        assert!(parse_positions("", geometry).is_err());
+        assert!(parse_positions(&format!("{}\n1,2,3", CSV_HEADER), geometry).is_err());
+    }
+}
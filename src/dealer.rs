@@ -0,0 +1,437 @@
+use crate::ai::{ai_move_with, Evaluator};
+use crate::board::possible_moves;
+use crate::brick::{brick_library, random_brick, random_brick_from, BrickId};
+use crate::game::{is_dead, GameState, RefillPolicy};
+use crate::log::Target;
+use crate::notation::format_bricks;
+use crate::search::Parallelism;
+use crate::trace;
+use rand::rngs::StdRng;
+use rand::SeedableRng;
+use std::collections::VecDeque;
+
+// Decides which bricks the player gets when the hand is refilled.
+pub trait Dealer {
+    // `count` is the number of bricks missing in the hand
+    fn deal(&mut self, game_state: &GameState, count: usize) -> Vec<BrickId>;
+
+    // whether the bricks can be dealt ahead of time for a preview, i.e. they don't depend on
+    // the board they'll be dealt onto
+    fn supports_preview(&self) -> bool {
+        true
+    }
+
+    // the most bricks dealt at once in a reasonable time
+    fn max_count(&self) -> usize {
+        usize::MAX
+    }
+}
+
+// Creates a dealer for a single game. Dealers which search get the bot's evaluator and parallelism.
+pub type DealerFactory = fn(&Evaluator, Parallelism) -> Box<dyn Dealer>;
+
+pub struct RandomDealer;
+
+impl Dealer for RandomDealer {
+    fn deal(&mut self, _game_state: &GameState, count: usize) -> Vec<BrickId> {
+        (0..count).map(|_| random_brick()).collect()
+    }
+}
+
+// Deals random bricks from a seeded generator, so games can be repeated.
+pub struct SeededDealer {
+    rng: StdRng,
+}
+
+impl SeededDealer {
+    pub fn new(seed: u64) -> SeededDealer {
+        SeededDealer {
+            rng: StdRng::seed_from_u64(seed),
+        }
+    }
+}
+
+impl Dealer for SeededDealer {
+    fn deal(&mut self, _game_state: &GameState, count: usize) -> Vec<BrickId> {
+        (0..count).map(|_| random_brick_from(&mut self.rng)).collect()
+    }
+}
+
+// Deals bricks which are hard to play on the current board and hand - not necessarily the worst.
+//
+// Checking every hand with the full search would take way too long, so all hands are
+// checked with `is_dead` first (a hand which can't be played ends the game), then only
+// `candidates` hands with the least possible moves are searched like the bot would.
+// Possible moves don't take clears into account, so the worst hand may not be among them.
+// The number of checked hands grows combinatorially with `count`, so at most
+// `MAX_ADVERSARIAL_COUNT` bricks are dealt at once.
+// Bricks dealt ahead for a preview would be picked against a board which changes before they're
+// dealt, so it can't be combined with a preview.
+pub struct AdversarialDealer {
+    // library bricks without duplicates (e.g. 4 rotations of a single cell are the same brick)
+    bricks: Vec<BrickId>,
+    candidates: usize,
+    evaluator: Evaluator,
+    parallelism: Parallelism,
+}
+
+// 4 bricks take a few seconds per hand already
+pub const MAX_ADVERSARIAL_COUNT: usize = 4;
+
+impl AdversarialDealer {
+    // the candidates are searched with the given evaluator and parallelism
+    pub fn new(candidates: usize, evaluator: Evaluator, parallelism: Parallelism) -> AdversarialDealer {
+        let mut bricks: Vec<BrickId> = vec![];
+        for brick in brick_library().ids() {
+            if !bricks.iter().any(|&b| same_shape(b, brick)) {
+                bricks.push(brick);
+            }
+        }
+
+        AdversarialDealer {
+            bricks,
+            candidates: candidates.max(1),
+            evaluator,
+            parallelism,
+        }
+    }
+
+    // all the ways of picking `count` bricks - order doesn't matter for the player
+    fn all_picks(&self, count: usize) -> Vec<Vec<BrickId>> {
+        let mut picks = vec![];
+        self.add_picks(count, 0, &mut vec![], &mut picks);
+        picks
+    }
+
+    fn add_picks(&self, count: usize, first: usize, pick: &mut Vec<BrickId>, picks: &mut Vec<Vec<BrickId>>) {
+        if pick.len() == count {
+            picks.push(pick.clone());
+            return;
+        }
+
+        for i in first..self.bricks.len() {
+            pick.push(self.bricks[i]);
+            self.add_picks(count, i, pick, picks);
+            pick.pop();
+        }
+    }
+}
+
+impl Dealer for AdversarialDealer {
+    fn deal(&mut self, game_state: &GameState, count: usize) -> Vec<BrickId> {
+        let mut candidates = vec![];
+        for pick in self.all_picks(count) {
+            let mut state = game_state.clone();
+            state.available_bricks.extend_from_slice(&pick);
+            if is_dead(&state) {
+                return pick;
+            }
+
+            let possible_move_count = pick
+                .iter()
+                .map(|brick| possible_moves(&game_state.board, brick.brick()).len())
+                .sum::<usize>();
+            candidates.push((possible_move_count, pick, state));
+        }
+
+        candidates.sort_by_key(|(possible_move_count, _, _)| *possible_move_count);
+        candidates
+            .into_iter()
+            .take(self.candidates)
+            // a hand without any moves is the worst possible one
+            .map(|(_, pick, state)| {
+                let score = ai_move_with(&state, &self.evaluator, self.parallelism).map_or(i32::MIN, |m| m.score);
+                (score, pick)
+            })
+            .min_by_key(|(score, _)| *score)
+            .unwrap()
+            .1
+    }
+
+    fn supports_preview(&self) -> bool {
+        false
+    }
+
+    fn max_count(&self) -> usize {
+        MAX_ADVERSARIAL_COUNT
+    }
+}
+
+// Remembers all the bricks dealt by another dealer, so they can be dealt again with `ScriptedDealer`.
+pub struct RecordingDealer<'a> {
+    dealer: &'a mut dyn Dealer,
+    pub dealt: Vec<BrickId>,
+}
+
+impl<'a> RecordingDealer<'a> {
+    pub fn new(dealer: &'a mut dyn Dealer) -> RecordingDealer<'a> {
+        RecordingDealer { dealer, dealt: vec![] }
+    }
+}
+
+impl Dealer for RecordingDealer<'_> {
+    fn deal(&mut self, game_state: &GameState, count: usize) -> Vec<BrickId> {
+        let bricks = self.dealer.deal(game_state, count);
+        self.dealt.extend_from_slice(&bricks);
+        bricks
+    }
+
+    fn supports_preview(&self) -> bool {
+        self.dealer.supports_preview()
+    }
+
+    fn max_count(&self) -> usize {
+        self.dealer.max_count()
+    }
+}
+
+// Deals the given bricks in order, then falls back to another dealer once they run out.
+// Lets two players play the same game.
+pub struct ScriptedDealer<'a> {
+    bricks: VecDeque<BrickId>,
+    fallback: &'a mut dyn Dealer,
+}
+
+impl<'a> ScriptedDealer<'a> {
+    pub fn new(bricks: Vec<BrickId>, fallback: &'a mut dyn Dealer) -> ScriptedDealer<'a> {
+        ScriptedDealer {
+            bricks: bricks.into(),
+            fallback,
+        }
+    }
+}
+
+impl Dealer for ScriptedDealer<'_> {
+    fn deal(&mut self, game_state: &GameState, count: usize) -> Vec<BrickId> {
+        let scripted = count.min(self.bricks.len());
+        let mut bricks = self.bricks.drain(..scripted).collect::<Vec<_>>();
+        if bricks.len() < count {
+            bricks.extend(self.fallback.deal(game_state, count - bricks.len()));
+        }
+        bricks
+    }
+
+    fn supports_preview(&self) -> bool {
+        self.fallback.supports_preview()
+    }
+
+    fn max_count(&self) -> usize {
+        self.fallback.max_count()
+    }
+}
+
+// Longer previews are refused by the command line and the server.
+pub const MAX_PREVIEW: usize = 64;
+
+// Deals bricks in advance, so the next `preview` bricks can be shown to the player
+// (and used by the bot) while the current hand is being placed. Only for dealers which
+// `supports_preview`.
+#[derive(Clone)]
+pub struct BrickQueue {
+    preview: usize,
+    queue: VecDeque<BrickId>,
+}
+
+impl BrickQueue {
+    pub fn new(preview: usize) -> BrickQueue {
+        BrickQueue {
+            preview,
+            queue: VecDeque::new(),
+        }
+    }
+
+    // Deals new bricks if the hand should be refilled according to the rules.
+    pub fn refill(&mut self, game_state: &mut GameState, dealer: &mut dyn Dealer) {
+        let hand_size = game_state.rules.hand_size;
+        let missing = match game_state.rules.refill_policy {
+            RefillPolicy::WhenEmpty if game_state.available_bricks.is_empty() => hand_size,
+            RefillPolicy::WhenEmpty => 0,
+            RefillPolicy::AfterEveryMove => hand_size.saturating_sub(game_state.available_bricks.len()),
+        };
+        if missing == 0 {
+            return;
+        }
+
+        let queued = missing.checked_add(self.preview).expect("the preview should be at most MAX_PREVIEW");
+        while self.queue.len() < queued {
+            let bricks = dealer.deal(game_state, missing);
+            self.queue.extend(bricks);
+        }
+
+        game_state.available_bricks.extend(self.queue.drain(..missing));
+        game_state.upcoming_bricks = self.queue.iter().take(self.preview).copied().collect();
+        trace!(
+            Target::Engine,
+            "dealt {} ({} left in the queue)",
+            format_bricks(&game_state.available_bricks[game_state.available_bricks.len() - missing..]),
+            self.queue.len()
+        );
+    }
+}
+
+fn same_shape(a: BrickId, b: BrickId) -> bool {
+    let cells = |brick: BrickId| {
+        let mut cells = brick.brick().offsets().iter().map(|v| (v.x, v.y)).collect::<Vec<_>>();
+        cells.sort_unstable();
+        cells
+    };
+    cells(a) == cells(b)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::brick::xy;
+    use crate::feasibility::hand_is_fully_placeable;
+    use crate::game::Rules;
+    use crate::Cell;
+
+    fn adversarial_dealer() -> AdversarialDealer {
+        AdversarialDealer::new(1, Evaluator::Heuristic, Parallelism::Off)
+    }
+
+    #[test]
+    fn test_random_dealer_deals_requested_bricks() {
+        assert_eq!(RandomDealer.deal(&GameState::new(), 3).len(), 3);
+        assert_eq!(RandomDealer.deal(&GameState::new(), 1).len(), 1);
+    }
+
+    #[test]
+    fn test_queue_without_preview() {
+        let mut game_state = GameState::new();
+        let mut queue = BrickQueue::new(0);
+        queue.refill(&mut game_state, &mut RandomDealer);
+
+        assert_eq!(game_state.available_bricks.len(), 3);
+        assert!(game_state.upcoming_bricks.is_empty());
+    }
+
+    #[test]
+    fn test_queue_deals_previewed_bricks() {
+        let mut game_state = GameState::new();
+        let mut queue = BrickQueue::new(4);
+        queue.refill(&mut game_state, &mut RandomDealer);
+
+        assert_eq!(game_state.available_bricks.len(), 3);
+        assert_eq!(game_state.upcoming_bricks.len(), 4);
+
+        // nothing changes until the hand is empty
+        let upcoming_bricks = game_state.upcoming_bricks.clone();
+        game_state.available_bricks.pop();
+        queue.refill(&mut game_state, &mut RandomDealer);
+        assert_eq!(game_state.available_bricks.len(), 2);
+        assert_eq!(game_state.upcoming_bricks, upcoming_bricks);
+
+        game_state.available_bricks.clear();
+        queue.refill(&mut game_state, &mut RandomDealer);
+        assert_eq!(game_state.available_bricks, upcoming_bricks[..3]);
+        assert_eq!(game_state.upcoming_bricks[0], upcoming_bricks[3]);
+        assert_eq!(game_state.upcoming_bricks.len(), 4);
+    }
+
+    #[test]
+    fn test_queue_with_custom_hand_size() {
+        let mut game_state = GameState::with_rules(Rules {
+            hand_size: 5,
+            refill_policy: RefillPolicy::WhenEmpty,
+            ..Rules::default()
+        });
+        let mut queue = BrickQueue::new(2);
+        queue.refill(&mut game_state, &mut RandomDealer);
+        assert_eq!(game_state.available_bricks.len(), 5);
+        assert_eq!(game_state.upcoming_bricks.len(), 2);
+
+        game_state.available_bricks.pop();
+        queue.refill(&mut game_state, &mut RandomDealer);
+        assert_eq!(game_state.available_bricks.len(), 4);
+    }
+
+    #[test]
+    fn test_queue_tops_up_after_every_move() {
+        let mut game_state = GameState::with_rules(Rules {
+            hand_size: 3,
+            refill_policy: RefillPolicy::AfterEveryMove,
+            ..Rules::default()
+        });
+        let mut queue = BrickQueue::new(1);
+        queue.refill(&mut game_state, &mut RandomDealer);
+        assert_eq!(game_state.available_bricks.len(), 3);
+
+        let upcoming_bricks = game_state.upcoming_bricks.clone();
+        game_state.available_bricks.remove(0);
+        queue.refill(&mut game_state, &mut RandomDealer);
+        assert_eq!(game_state.available_bricks.len(), 3);
+        assert_eq!(game_state.available_bricks[2], upcoming_bricks[0]);
+        assert_eq!(game_state.upcoming_bricks.len(), 1);
+    }
+
+    #[test]
+    fn test_seeded_dealer_repeats_bricks() {
+        let game_state = GameState::new();
+        let bricks = SeededDealer::new(7).deal(&game_state, 10);
+        assert_eq!(SeededDealer::new(7).deal(&game_state, 10), bricks);
+    }
+
+    #[test]
+    fn test_scripted_dealer_replays_recorded_bricks() {
+        let game_state = GameState::new();
+        let mut random_dealer = RandomDealer;
+        let mut recording_dealer = RecordingDealer::new(&mut random_dealer);
+        let first = recording_dealer.deal(&game_state, 3);
+        let second = recording_dealer.deal(&game_state, 2);
+        let dealt = recording_dealer.dealt;
+        assert_eq!(dealt.len(), 5);
+
+        let mut fallback = RandomDealer;
+        let mut scripted_dealer = ScriptedDealer::new(dealt, &mut fallback);
+        assert_eq!(scripted_dealer.deal(&game_state, 3), first);
+        let rest = scripted_dealer.deal(&game_state, 3);
+        assert_eq!(rest[..2], second[..]);
+        assert_eq!(rest.len(), 3);
+    }
+
+    #[test]
+    fn test_adversarial_dealer_uses_unique_bricks() {
+        let dealer = adversarial_dealer();
+        for (i, &a) in dealer.bricks.iter().enumerate() {
+            for &b in &dealer.bricks[i + 1..] {
+                assert!(!same_shape(a, b));
+            }
+        }
+    }
+
+    #[test]
+    fn test_adversarial_dealer_ends_the_game_if_possible() {
+        // there is no space for bricks longer than 1 cell
+        let mut game_state = GameState::new();
+        for x in 0..9 {
+            for y in 0..9 {
+                if (x + y) % 2 == 1 {
+                    game_state.board.set(xy(x, y), Cell::Filled);
+                }
+            }
+        }
+
+        let hand = adversarial_dealer().deal(&game_state, 3);
+        assert_eq!(hand.len(), 3);
+        assert!(!hand_is_fully_placeable(&game_state.board, &hand));
+    }
+
+    #[test]
+    fn test_adversarial_dealer_refuses_preview() {
+        let mut dealer = adversarial_dealer();
+        assert!(!dealer.supports_preview());
+        assert!(!RecordingDealer::new(&mut dealer).supports_preview());
+        assert!(!ScriptedDealer::new(vec![], &mut dealer).supports_preview());
+        assert!(RandomDealer.supports_preview());
+        assert!(SeededDealer::new(0).supports_preview());
+    }
+
+    #[test]
+    fn test_adversarial_dealer_limits_the_count() {
+        let mut dealer = adversarial_dealer();
+        assert_eq!(dealer.max_count(), MAX_ADVERSARIAL_COUNT);
+        assert_eq!(RecordingDealer::new(&mut dealer).max_count(), MAX_ADVERSARIAL_COUNT);
+        assert_eq!(RandomDealer.max_count(), usize::MAX);
+    }
+}
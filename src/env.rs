@@ -0,0 +1,209 @@
+use crate::board::can_put_brick;
+use crate::brick::{xy, BrickId};
+use crate::dealer::{BrickQueue, SeededDealer};
+use crate::error::Error;
+use crate::game::{is_game_over, perform_move, GameState, Move, Rules};
+use crate::mlp::board_features;
+use crate::Board;
+
+// All the bricks fit in a 5x5 square.
+pub const BRICK_GRID_SIZE: usize = 5;
+
+// Reinforcement learning environment with a fixed action space - an action is a brick index and
+// a position on the board: `brick_index * cells + y * size + x`. Holding bricks isn't supported.
+//
+// Observations are flat vectors of:
+// - board features (see `board_features`),
+// - cells of each brick in the hand on a 5x5 grid (all zeros for used bricks).
+pub struct Env {
+    rules: Rules,
+    start_board: Board,
+    game_state: GameState,
+    queue: BrickQueue,
+    dealer: SeededDealer,
+}
+
+pub struct Step {
+    pub observation: Vec<f32>,
+    // points scored by the action
+    pub reward: f32,
+    pub done: bool,
+}
+
+impl Env {
+    pub fn new(rules: Rules, start_board: Board) -> Env {
+        let rules = Rules { hold: false, ..rules };
+        Env {
+            rules,
+            start_board,
+            game_state: GameState::with_rules(rules),
+            queue: BrickQueue::new(0),
+            dealer: SeededDealer::new(0),
+        }
+    }
+
+    pub fn cell_count(&self) -> usize {
+        let size = self.start_board.size() as usize;
+        size * size
+    }
+
+    pub fn action_count(&self) -> usize {
+        self.rules.hand_size * self.cell_count()
+    }
+
+    pub fn observation_size(&self) -> usize {
+        2 * self.cell_count() + self.rules.hand_size * BRICK_GRID_SIZE * BRICK_GRID_SIZE
+    }
+
+    pub fn game_state(&self) -> &GameState {
+        &self.game_state
+    }
+
+    // starts a new game, the same seed gives the same bricks
+    pub fn reset(&mut self, seed: u64) -> Vec<f32> {
+        self.game_state = GameState::with_rules(self.rules);
+        self.game_state.board = self.start_board;
+        self.queue = BrickQueue::new(0);
+        self.dealer = SeededDealer::new(seed);
+        self.queue.refill(&mut self.game_state, &mut self.dealer);
+        self.observation()
+    }
+
+    // fails with `NoLegalMoves` if the game is over - call `reset` then
+    pub fn step(&mut self, action: usize) -> Result<Step, Error> {
+        if self.is_done() {
+            return Err(Error::NoLegalMoves);
+        }
+        let m = self
+            .decode_action(action)
+            .ok_or_else(|| Error::IllegalMove(format!("action {} is out of the action space", action)))?;
+
+        let points = self.game_state.points;
+        self.game_state = perform_move(&self.game_state, &m)?;
+        self.queue.refill(&mut self.game_state, &mut self.dealer);
+
+        Ok(Step {
+            observation: self.observation(),
+            reward: (self.game_state.points - points) as f32,
+            done: self.is_done(),
+        })
+    }
+
+    pub fn is_done(&self) -> bool {
+        is_game_over(&self.game_state)
+    }
+
+    pub fn legal_action_mask(&self) -> Vec<bool> {
+        (0..self.action_count())
+            .map(|action| match self.decode_action(action) {
+                Some(m) => self.is_legal(&m),
+                None => false,
+            })
+            .collect()
+    }
+
+    pub fn encode_action(&self, m: &Move) -> usize {
+        let size = self.start_board.size() as usize;
+        m.brick_index as usize * self.cell_count() + m.pos.y as usize * size + m.pos.x as usize
+    }
+
+    // None if the action is out of the action space
+    pub fn decode_action(&self, action: usize) -> Option<Move> {
+        if action >= self.action_count() {
+            return None;
+        }
+        let size = self.start_board.size() as usize;
+        let cell = action % self.cell_count();
+        Some(Move {
+            brick_index: (action / self.cell_count()) as i32,
+            pos: xy((cell % size) as i32, (cell / size) as i32),
+        })
+    }
+
+    fn is_legal(&self, m: &Move) -> bool {
+        match self.game_state.available_bricks.get(m.brick_index as usize) {
+            Some(brick) => can_put_brick(&self.game_state.board, brick.brick(), &m.pos),
+            None => false,
+        }
+    }
+
+    pub fn observation(&self) -> Vec<f32> {
+        let mut observation = board_features(&self.game_state.board);
+        for brick_index in 0..self.rules.hand_size {
+            observation.extend(brick_grid(self.game_state.available_bricks.get(brick_index)));
+        }
+        observation
+    }
+}
+
+fn brick_grid(brick: Option<&BrickId>) -> Vec<f32> {
+    let mut grid = vec![0.0; BRICK_GRID_SIZE * BRICK_GRID_SIZE];
+    if let Some(brick) = brick {
+        for v in brick.brick().offsets() {
+            grid[v.y as usize * BRICK_GRID_SIZE + v.x as usize] = 1.0;
+        }
+    }
+    grid
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::brick::brick_library;
+
+    #[test]
+    fn test_bricks_fit_in_the_grid() {
+        for brick in brick_library().bricks() {
+            assert!(brick.offsets().iter().all(|v| (v.x as usize) < BRICK_GRID_SIZE && (v.y as usize) < BRICK_GRID_SIZE));
+        }
+    }
+
+    #[test]
+    fn test_reset_is_repeatable() {
+        let mut env = Env::new(Rules::default(), Board::default());
+        let observation = env.reset(42);
+        assert_eq!(observation.len(), env.observation_size());
+        assert_eq!(env.action_count(), 3 * 81);
+        assert_eq!(env.reset(42), observation);
+    }
+
+    #[test]
+    fn test_step_with_legal_actions() {
+        let mut env = Env::new(Rules::default(), Board::default());
+        env.reset(1);
+
+        let mut total_reward = 0.0;
+        loop {
+            let mask = env.legal_action_mask();
+            let action = match mask.iter().position(|&legal| legal) {
+                Some(action) => action,
+                None => break,
+            };
+            let step = env.step(action).unwrap();
+            total_reward += step.reward;
+            assert_eq!(step.observation.len(), env.observation_size());
+            if step.done {
+                break;
+            }
+        }
+
+        assert!(env.is_done());
+        assert_eq!(total_reward, env.game_state().points as f32);
+        assert_eq!(env.step(0).err(), Some(Error::NoLegalMoves));
+    }
+
+    #[test]
+    fn test_illegal_actions() {
+        let mut env = Env::new(Rules::default(), Board::default());
+        env.reset(1);
+        assert!(env.step(env.action_count()).is_err());
+
+        // the hand is refilled only when it's empty
+        let m = Move {
+            brick_index: 2,
+            pos: xy(0, 0),
+        };
+        env.step(env.encode_action(&m)).unwrap();
+        assert!(matches!(env.step(env.encode_action(&m)), Err(Error::IllegalMove(_))));
+    }
+}
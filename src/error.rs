@@ -0,0 +1,30 @@
+use std::fmt;
+
+// Errors of the engine's APIs. Invalid input (e.g. a move sent by a client) never panics,
+// it's reported with one of these.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum Error {
+    // the move or action can't be performed in the given state
+    IllegalMove(String),
+    MalformedBrick(String),
+    // the player has nothing to do - the game is over
+    NoLegalMoves,
+    // text which can't be parsed (e.g. a move in the standard notation)
+    Parse(String),
+    // a file which can't be read or a process which can't be talked to
+    Io(String),
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Error::IllegalMove(reason) => write!(f, "illegal move: {}", reason),
+            Error::MalformedBrick(reason) => write!(f, "malformed brick: {}", reason),
+            Error::NoLegalMoves => write!(f, "there are no legal moves"),
+            Error::Parse(reason) => write!(f, "{}", reason),
+            Error::Io(reason) => write!(f, "{}", reason),
+        }
+    }
+}
+
+impl std::error::Error for Error {}
@@ -0,0 +1,237 @@
+use crate::board::{can_put_brick, possible_moves, put_brick};
+use crate::brick::{BrickId, XY};
+use crate::metrics::{self, Counter};
+use crate::resolve::resolve_board;
+use crate::{Board, Cell};
+use std::collections::HashSet;
+
+// Checks if all the bricks from the hand can be put on the board - in some order, with
+// lines/blocks being cleared between the moves, like in a real game.
+pub fn hand_is_fully_placeable(board: &Board, hand: &[BrickId]) -> bool {
+    // most of the time the bricks just fit, which is much cheaper to check
+    if fits_greedily(board, hand) {
+        return true;
+    }
+
+    let mut dead_positions = HashSet::new();
+    let remaining = (0..hand.len()).collect::<Vec<_>>();
+    search(board, hand, &remaining, &mut dead_positions)
+}
+
+// Puts the bricks one by one in the first free spot.
+fn fits_greedily(board: &Board, hand: &[BrickId]) -> bool {
+    let mut board = *board;
+    for brick in hand.iter().map(|id| id.brick()) {
+        let new_board = board
+            .positions()
+            .find(|pos| can_put_brick(&board, brick, pos))
+            .and_then(|pos| put_brick(&board, brick, &pos).ok());
+        match new_board {
+            Some(new_board) => board = resolve_board(new_board).board,
+            None => return false,
+        }
+    }
+
+    true
+}
+
+// `dead_positions` remembers (board, remaining bricks) pairs already proven unsolvable,
+// as different move orders often lead to the same position.
+fn search(
+    board: &Board,
+    hand: &[BrickId],
+    remaining: &[usize],
+    dead_positions: &mut HashSet<(Board, Vec<usize>)>,
+) -> bool {
+    if remaining.is_empty() {
+        return true;
+    }
+
+    if dead_positions.contains(&(*board, remaining.to_vec())) {
+        metrics::inc(Counter::TranspositionHits);
+        return false;
+    }
+
+    let moves = remaining
+        .iter()
+        .map(|i| (*i, possible_moves(board, hand[*i].brick())))
+        .collect::<Vec<_>>();
+
+    // The last brick only needs a single spot.
+    if remaining.len() == 1 {
+        return !moves[0].1.is_empty();
+    }
+
+    if stuck_for_good(board, hand, &moves) {
+        dead_positions.insert((*board, remaining.to_vec()));
+        return false;
+    }
+
+    // the most constrained bricks first - it makes dead ends show up sooner
+    let mut order = moves;
+    order.sort_by_key(|(_, positions)| positions.len());
+
+    for (brick_index, positions) in &order {
+        let brick = hand[*brick_index].brick();
+        let rest = remaining
+            .iter()
+            .cloned()
+            .filter(|i| i != brick_index)
+            .collect::<Vec<_>>();
+
+        for pos in positions {
+            let resolve_result = match put_brick(board, brick, pos) {
+                Ok(new_board) => resolve_board(new_board),
+                Err(_) => continue,
+            };
+            if search(&resolve_result.board, hand, &rest, dead_positions) {
+                return true;
+            }
+        }
+    }
+
+    dead_positions.insert((*board, remaining.to_vec()));
+    false
+}
+
+// A brick without a spot can only be put on the board after some region is cleared, and until
+// then the other bricks only add cells. If they don't have enough cells to fill up even the
+// emptiest region, nothing gets cleared and the stuck brick never fits.
+fn stuck_for_good(board: &Board, hand: &[BrickId], moves: &[(usize, Vec<XY>)]) -> bool {
+    if moves.iter().all(|(_, positions)| !positions.is_empty()) {
+        return false;
+    }
+
+    let placeable_cells = moves
+        .iter()
+        .filter(|(_, positions)| !positions.is_empty())
+        .map(|(i, _)| hand[*i].brick().offsets().len())
+        .sum::<usize>();
+    let fewest_empty_cells = board
+        .geometry()
+        .regions
+        .iter()
+        // regions of stones are never cleared
+        .filter(|region| board.region(region).any(|cell| cell != Cell::Stone))
+        .map(|region| board.region(region).filter(|cell| !cell.is_filled()).count())
+        .min();
+    match fewest_empty_cells {
+        Some(fewest_empty_cells) => placeable_cells < fewest_empty_cells,
+        None => true,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::brick::xy;
+    use crate::Cell;
+
+    // lines are the first shapes of the library
+    fn line(len: usize) -> BrickId {
+        BrickId::new(len - 1, 0).unwrap()
+    }
+
+    fn dot() -> BrickId {
+        line(1)
+    }
+
+    #[test]
+    fn test_empty_hand_is_placeable() {
+        assert!(hand_is_fully_placeable(&Board::default(), &[]));
+    }
+
+    #[test]
+    fn test_hand_on_empty_board_is_placeable() {
+        let hand = vec![line(5), line(5), line(5)];
+        assert!(hand_is_fully_placeable(&Board::default(), &hand));
+    }
+
+    fn full_board() -> Board {
+        let mut board = Board::default();
+        for x in 0..9 {
+            for y in 0..9 {
+                board.set(xy(x, y), Cell::Filled);
+            }
+        }
+        board
+    }
+
+    // every other cell is filled, so no 2 free cells are next to each other
+    // and no row, column or block is close to being cleared
+    fn checkerboard() -> Board {
+        let mut board = Board::default();
+        for x in 0..9 {
+            for y in 0..9 {
+                if (x + y) % 2 == 1 {
+                    board.set(xy(x, y), Cell::Filled);
+                }
+            }
+        }
+        board
+    }
+
+    #[test]
+    fn test_not_enough_space() {
+        let board = checkerboard();
+
+        assert!(!hand_is_fully_placeable(&board, &[line(2)]));
+        assert!(hand_is_fully_placeable(&board, &[dot(), dot(), dot()]));
+    }
+
+    #[test]
+    fn test_clear_makes_space_for_next_brick() {
+        // row 0 is missing 1 cell, everything else is full apart from cell (4, 4)
+        let mut board = full_board();
+        board.set(xy(8, 0), Cell::Empty);
+        board.set(xy(4, 4), Cell::Empty);
+
+        // the 2-cell line fits only after the dot completes row 0 (and column 8)
+        assert!(hand_is_fully_placeable(&board, &[line(2), dot()]));
+        assert!(hand_is_fully_placeable(&board, &[dot(), line(2)]));
+    }
+
+    #[test]
+    fn test_only_one_spot_for_line() {
+        // cells a1, b1 and c1 are free
+        let mut board = checkerboard();
+        board.set(xy(1, 0), Cell::Empty);
+
+        assert!(hand_is_fully_placeable(&board, &[dot(), line(2), dot()]));
+        assert!(!hand_is_fully_placeable(&board, &[line(2), line(2)]));
+    }
+
+    #[test]
+    fn test_clear_needs_moves_clearing_nothing_first() {
+        // row 0 is missing b1 and h1, the rest is a checkerboard - the line fits only after
+        // both dots clear row 0, and the first dot doesn't clear anything yet
+        let mut board = checkerboard();
+        for x in 0..9 {
+            board.set(xy(x, 0), Cell::Filled);
+        }
+        board.set(xy(1, 0), Cell::Empty);
+        board.set(xy(7, 0), Cell::Empty);
+
+        assert!(hand_is_fully_placeable(&board, &[line(2), dot(), dot()]));
+        assert!(!hand_is_fully_placeable(&board, &[line(2), dot()]));
+
+        // the stuck line isn't given up on as long as the dots could clear row 0
+        let moves = |hand: &[BrickId]| {
+            (0..hand.len()).map(|i| (i, possible_moves(&board, hand[i].brick()))).collect::<Vec<_>>()
+        };
+        let hand = [line(2), dot(), dot()];
+        assert!(!stuck_for_good(&board, &hand, &moves(&hand)));
+        let hand = [line(2), dot()];
+        assert!(stuck_for_good(&board, &hand, &moves(&hand)));
+    }
+
+    #[test]
+    fn test_stuck_brick_on_checkerboard() {
+        // no region is less than 4 cells from being cleared
+        let board = checkerboard();
+        let hand = [line(2), dot(), dot(), dot()];
+        assert!(!hand_is_fully_placeable(&board, &hand));
+        let moves = (0..hand.len()).map(|i| (i, possible_moves(&board, hand[i].brick()))).collect::<Vec<_>>();
+        assert!(stuck_for_good(&board, &hand, &moves));
+    }
+}
@@ -0,0 +1,386 @@
+use crate::board::{possible_moves, put_brick};
+use crate::brick::{BrickId, XY};
+use crate::error::Error;
+use crate::feasibility::hand_is_fully_placeable;
+use crate::geometry::REGION_KINDS;
+use crate::resolve::resolve_board;
+use crate::Board;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum RefillPolicy {
+    // new bricks are dealt when all the bricks from the hand are used
+    WhenEmpty,
+    // the hand is topped up to its full size after every move
+    AfterEveryMove,
+}
+
+impl RefillPolicy {
+    // names used in the command line options and the server's protocol
+    pub fn from_name(name: &str) -> Option<RefillPolicy> {
+        match name {
+            "empty" => Some(RefillPolicy::WhenEmpty),
+            "every-move" => Some(RefillPolicy::AfterEveryMove),
+            _ => None,
+        }
+    }
+}
+
+// The biggest hands searched on bitboards, where the bricks left in the hand are bits of a `u32`.
+// Bigger hands are searched with the reference search, which is way slower.
+pub const MAX_HAND_SIZE: usize = 16;
+
+#[derive(Debug, Clone, Copy)]
+pub struct Rules {
+    pub hand_size: usize,
+    pub refill_policy: RefillPolicy,
+    // allows parking a brick from the hand in the hold slot (and swapping it back later)
+    pub hold: bool,
+}
+
+impl Default for Rules {
+    fn default() -> Self {
+        Rules {
+            hand_size: 3,
+            refill_policy: RefillPolicy::WhenEmpty,
+            hold: false,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct GameState {
+    pub board: Board,
+    pub available_bricks: Vec<BrickId>,
+    // bricks which will be dealt next, visible to the player (empty when playing without preview)
+    pub upcoming_bricks: Vec<BrickId>,
+    pub points: i32,
+    pub last_move_was_match: bool,
+    pub held_brick: Option<BrickId>,
+    // only a single hold is allowed between putting bricks on the board
+    pub hold_used: bool,
+    pub rules: Rules,
+    // number of regions cleared so far, indexed by `RegionKind`
+    pub cleared_regions: [i32; REGION_KINDS.len()],
+}
+
+impl GameState {
+    pub fn new() -> GameState {
+        GameState::with_rules(Rules::default())
+    }
+
+    pub fn with_rules(rules: Rules) -> GameState {
+        GameState {
+            board: Board::default(),
+            points: 0,
+            last_move_was_match: false,
+            available_bricks: vec![],
+            upcoming_bricks: vec![],
+            held_brick: None,
+            hold_used: false,
+            rules,
+            cleared_regions: [0; REGION_KINDS.len()],
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct Move {
+    // 0-based index
+    pub brick_index: i32,
+    // 0-based axes
+    pub pos: XY,
+}
+
+fn newly_filled_cells_count(new_board: &Board, old_board: &Board) -> i32 {
+    new_board
+        .rows()
+        .flatten()
+        .zip(old_board.rows().flatten())
+        .filter(|(new, old)| new.is_filled() && !old.is_filled())
+        .count() as i32
+}
+
+// Points:
+// 18 points per cleared region (row, column, box or diagonal)
+// 1 point for each cell that is filled after current move, that wasn't filled before current move
+// if previous move was a line +9 points extra (streak)
+
+pub fn perform_move(game_state: &GameState, m: &Move) -> Result<GameState, Error> {
+    check_brick_index(game_state, m.brick_index)?;
+
+    let mut available_bricks = game_state.available_bricks.clone();
+    let brick = available_bricks.remove(m.brick_index as usize);
+    let board = put_brick(&game_state.board, brick.brick(), &m.pos)?;
+    let resolve_result = resolve_board(board);
+    let board = resolve_result.board;
+
+    let streak = game_state.last_move_was_match && resolve_result.blocks_removed > 0;
+    let streak_points = if streak { 9 } else { 0 };
+    let new_cells_points = newly_filled_cells_count(&board, &game_state.board);
+    let bonus_points = resolve_result.blocks_removed * 18 + streak_points + new_cells_points;
+    let mut cleared_regions = game_state.cleared_regions;
+    for (cleared, removed) in cleared_regions.iter_mut().zip(resolve_result.removed_by_kind.iter()) {
+        *cleared += removed;
+    }
+
+    Ok(GameState {
+        board,
+        points: game_state.points + bonus_points,
+        last_move_was_match: resolve_result.blocks_removed > 0,
+        available_bricks,
+        upcoming_bricks: game_state.upcoming_bricks.clone(),
+        held_brick: game_state.held_brick,
+        hold_used: false,
+        rules: game_state.rules,
+        cleared_regions,
+    })
+}
+
+fn check_brick_index(game_state: &GameState, brick_index: i32) -> Result<(), Error> {
+    let brick_count = game_state.available_bricks.len() as i32;
+    if brick_index < 0 || brick_index >= brick_count {
+        return Err(Error::IllegalMove(format!(
+            "no brick at index {} - the hand has {} bricks",
+            brick_index, brick_count
+        )));
+    }
+    Ok(())
+}
+
+pub fn can_hold(game_state: &GameState) -> bool {
+    game_state.rules.hold && !game_state.hold_used && !game_state.available_bricks.is_empty()
+}
+
+// Puts the brick in the hold slot. The brick held before (if any) takes its place in the hand.
+pub fn hold_brick(game_state: &GameState, brick_index: i32) -> Result<GameState, Error> {
+    if !can_hold(game_state) {
+        return Err(Error::IllegalMove(
+            "holding is allowed once between putting bricks on the board".to_string(),
+        ));
+    }
+    check_brick_index(game_state, brick_index)?;
+
+    let mut s = game_state.clone();
+    let brick = match s.held_brick.take() {
+        Some(held_brick) => std::mem::replace(&mut s.available_bricks[brick_index as usize], held_brick),
+        None => s.available_bricks.remove(brick_index as usize),
+    };
+    s.held_brick = Some(brick);
+    s.hold_used = true;
+    Ok(s)
+}
+
+#[derive(Debug, Clone)]
+pub enum Action {
+    Place(Move),
+    Hold { brick_index: i32 },
+}
+
+impl Action {
+    // the brick from the hand which is placed or held
+    pub fn brick_index(&self) -> i32 {
+        match self {
+            Action::Place(m) => m.brick_index,
+            Action::Hold { brick_index } => *brick_index,
+        }
+    }
+}
+
+pub fn perform_action(game_state: &GameState, action: &Action) -> Result<GameState, Error> {
+    match action {
+        Action::Place(m) => perform_move(game_state, m),
+        Action::Hold { brick_index } => hold_brick(game_state, *brick_index),
+    }
+}
+
+#[derive(Clone, Debug)]
+pub struct PossibleMove {
+    pub brick: BrickId,
+    pub brick_index: i32,
+    pub pos: XY,
+}
+
+impl From<PossibleMove> for Move {
+    fn from(m: PossibleMove) -> Self {
+        Move{pos: m.pos, brick_index: m.brick_index}
+    }
+}
+
+pub fn get_possible_moves(game_state: &GameState) -> Vec<PossibleMove> {
+    let mut moves = vec![];
+    for (brick_index, &brick) in game_state.available_bricks.iter().enumerate() {
+        moves.extend(
+            possible_moves(&game_state.board, brick.brick())
+                .iter()
+                .map(|pos| PossibleMove {
+                    brick,
+                    brick_index: brick_index as i32,
+                    pos: *pos,
+                })
+                .collect::<Vec<_>>(),
+        );
+    }
+
+    moves
+}
+
+// Checks if the game is lost no matter how the current hand is played.
+pub fn is_dead(game_state: &GameState) -> bool {
+    match game_state.rules.refill_policy {
+        // all the bricks have to be placed before new ones are dealt
+        RefillPolicy::WhenEmpty => {
+            !hand_is_fully_placeable(&game_state.board, &game_state.available_bricks)
+        }
+        // a new brick comes after every move, so it's enough to place any brick
+        RefillPolicy::AfterEveryMove => {
+            !game_state.available_bricks.is_empty() && get_possible_moves(game_state).is_empty()
+        }
+    }
+}
+
+// Checks if the player can't put any brick on the board anymore.
+pub fn is_game_over(game_state: &GameState) -> bool {
+    if !get_possible_moves(game_state).is_empty() {
+        return false;
+    }
+
+    if !can_hold(game_state) {
+        return true;
+    }
+
+    match &game_state.held_brick {
+        // holding doesn't change the board, so it only helps if the held brick fits
+        Some(brick) => possible_moves(&game_state.board, brick.brick()).is_empty(),
+        // parking a brick helps if it makes the hand refilled with new bricks
+        None => {
+            game_state.rules.refill_policy == RefillPolicy::WhenEmpty
+                && game_state.available_bricks.len() > 1
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::brick::xy;
+    use crate::dealer::{BrickQueue, SeededDealer};
+    use crate::testing;
+    use crate::Cell;
+    use proptest::prelude::*;
+    use rand::rngs::StdRng;
+    use rand::{Rng, SeedableRng};
+
+    // lines are the first shapes of the library
+    fn line(len: usize) -> BrickId {
+        BrickId::new(len - 1, 0).unwrap()
+    }
+
+    fn game_with_hold(hand: Vec<BrickId>) -> GameState {
+        let mut game_state = GameState::with_rules(Rules {
+            hold: true,
+            ..Rules::default()
+        });
+        game_state.available_bricks = hand;
+        game_state
+    }
+
+    #[test]
+    fn test_hold_and_swap_back() {
+        let game_state = game_with_hold(vec![line(1), line(2), line(3)]);
+
+        let s = hold_brick(&game_state, 1).unwrap();
+        assert_eq!(s.held_brick, Some(line(2)));
+        assert_eq!(s.available_bricks, vec![line(1), line(3)]);
+        assert!(!can_hold(&s));
+
+        assert!(hold_brick(&s, 0).is_err());
+        let s = perform_move(&s, &Move { brick_index: 0, pos: xy(0, 0) }).unwrap();
+        assert!(can_hold(&s));
+
+        let s = hold_brick(&s, 0).unwrap();
+        assert_eq!(s.held_brick, Some(line(3)));
+        assert_eq!(s.available_bricks, vec![line(2)]);
+    }
+
+    #[test]
+    fn test_illegal_moves_are_errors() {
+        let game_state = game_with_hold(vec![line(5)]);
+        let illegal_moves = [
+            Move { brick_index: 1, pos: xy(0, 0) },
+            Move { brick_index: -1, pos: xy(0, 0) },
+            Move { brick_index: 0, pos: xy(5, 0) },
+            Move { brick_index: 0, pos: xy(-1, 3) },
+        ];
+        for m in &illegal_moves {
+            assert!(matches!(perform_move(&game_state, m), Err(Error::IllegalMove(_))), "{:?}", m);
+        }
+        assert!(hold_brick(&game_state, 3).is_err());
+    }
+
+    #[test]
+    fn test_hold_is_disabled_by_default() {
+        let mut game_state = GameState::new();
+        game_state.available_bricks = vec![line(1)];
+        assert!(!can_hold(&game_state));
+    }
+
+    #[test]
+    fn test_held_brick_can_save_the_game() {
+        // only a single cell is free
+        let mut board = Board::default();
+        for x in 0..9 {
+            for y in 0..9 {
+                board.set(xy(x, y), Cell::Filled);
+            }
+        }
+        board.set(xy(4, 4), Cell::Empty);
+
+        let mut game_state = game_with_hold(vec![line(2)]);
+        game_state.board = board;
+        game_state.held_brick = Some(line(1));
+        assert!(!is_game_over(&game_state));
+
+        game_state.held_brick = Some(line(3));
+        assert!(is_game_over(&game_state));
+
+        game_state.held_brick = Some(line(1));
+        game_state.hold_used = true;
+        assert!(is_game_over(&game_state));
+    }
+
+    proptest! {
+        // random games: every move scores and clearing regions is rewarded
+        #[test]
+        fn test_points_never_decrease(seed in any::<u64>(), geometry in testing::geometry(), hold in any::<bool>()) {
+            let mut rng = StdRng::seed_from_u64(seed);
+            let mut dealer = SeededDealer::new(seed);
+            let mut queue = BrickQueue::new(0);
+            let mut game_state = GameState::with_rules(Rules { hold, ..Rules::default() });
+            game_state.board = Board::new(geometry);
+
+            for _ in 0..30 {
+                queue.refill(&mut game_state, &mut dealer);
+                let moves = get_possible_moves(&game_state);
+                if moves.is_empty() {
+                    break;
+                }
+                if can_hold(&game_state) && rng.gen_bool(0.2) {
+                    let s = hold_brick(&game_state, 0).unwrap();
+                    prop_assert_eq!(s.points, game_state.points);
+                    game_state = s;
+                    continue;
+                }
+
+                let m = &moves[rng.gen_range(0, moves.len())];
+                let s = perform_move(&game_state, &m.clone().into()).unwrap();
+                let blocks_removed = s.cleared_regions.iter().sum::<i32>() - game_state.cleared_regions.iter().sum::<i32>();
+                let gained = s.points - game_state.points;
+                prop_assert!(gained >= 1);
+                prop_assert!(gained >= 18 * blocks_removed);
+                prop_assert_eq!(s.last_move_was_match, blocks_removed > 0);
+                prop_assert_eq!(s.available_bricks.len(), game_state.available_bricks.len() - 1);
+                game_state = s;
+            }
+        }
+    }
+}
@@ -0,0 +1,299 @@
+use crate::brick::{xy, XY};
+use crate::error::Error;
+use crate::Occupancy;
+use lazy_static::lazy_static;
+
+// Boards are stored in fixed size arrays, so they can be copied around cheaply.
+pub const MAX_BOARD_SIZE: i32 = 16;
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Hash)]
+pub enum RegionKind {
+    Row,
+    Column,
+    // square boxes or irregular (jigsaw) ones
+    Box,
+    Diagonal,
+}
+
+pub const REGION_KINDS: [RegionKind; 4] = [
+    RegionKind::Row,
+    RegionKind::Column,
+    RegionKind::Box,
+    RegionKind::Diagonal,
+];
+
+impl RegionKind {
+    pub fn name(self) -> &'static str {
+        match self {
+            RegionKind::Row => "rows",
+            RegionKind::Column => "columns",
+            RegionKind::Box => "boxes",
+            RegionKind::Diagonal => "diagonals",
+        }
+    }
+}
+
+#[derive(Debug, PartialEq, Eq)]
+pub struct Region {
+    pub kind: RegionKind,
+    pub cells: Vec<XY>,
+}
+
+// Size of a (square) board and the regions which are cleared once all their cells are filled.
+#[derive(Debug, PartialEq, Eq)]
+pub struct Geometry {
+    pub size: i32,
+    // 0 if the board isn't divided into square boxes
+    pub box_size: i32,
+    pub regions: Vec<Region>,
+    // the cells of every region as bitboards, for the search - set by `new` and `parse_regions`
+    masks: Vec<Occupancy>,
+}
+
+lazy_static! {
+    static ref STANDARD: Geometry = Geometry::new(9, 3).unwrap();
+}
+
+impl Geometry {
+    // Regions are all the rows, columns and boxes.
+    pub fn new(size: i32, box_size: i32) -> Result<Geometry, Error> {
+        check_size(size)?;
+        if box_size < 0 || (box_size > 0 && size % box_size != 0) {
+            return Err(Error::Parse(format!(
+                "board size ({}) should be divisible by box size ({})",
+                size, box_size
+            )));
+        }
+
+        let mut regions = lines(size);
+        if box_size > 0 {
+            for box_y in 0..size / box_size {
+                for box_x in 0..size / box_size {
+                    let mut cells = vec![];
+                    for y in 0..box_size {
+                        for x in 0..box_size {
+                            cells.push(xy(box_x * box_size + x, box_y * box_size + y));
+                        }
+                    }
+                    regions.push(Region {
+                        kind: RegionKind::Box,
+                        cells,
+                    });
+                }
+            }
+        }
+
+        let masks = masks(&regions);
+        Ok(Geometry {
+            size,
+            box_size,
+            regions,
+            masks,
+        })
+    }
+
+    // 9x9 board with 3x3 boxes
+    pub fn standard() -> &'static Geometry {
+        &STANDARD
+    }
+
+    pub fn region_masks(&self) -> &[Occupancy] {
+        &self.masks
+    }
+
+    // Boards keep a reference to their geometry, so it has to live until the end of the program.
+    pub fn leak(self) -> &'static Geometry {
+        Box::leak(Box::new(self))
+    }
+}
+
+fn check_size(size: i32) -> Result<(), Error> {
+    if !(1..=MAX_BOARD_SIZE).contains(&size) {
+        return Err(Error::Parse(format!("board size should be between 1 and {}", MAX_BOARD_SIZE)));
+    }
+    Ok(())
+}
+
+fn masks(regions: &[Region]) -> Vec<Occupancy> {
+    regions
+        .iter()
+        .map(|region| {
+            let mut rows = Occupancy::default();
+            for pos in &region.cells {
+                rows[pos.y as usize] |= 1 << pos.x;
+            }
+            rows
+        })
+        .collect()
+}
+
+fn lines(size: i32) -> Vec<Region> {
+    let mut regions = vec![];
+    for y in 0..size {
+        regions.push(Region {
+            kind: RegionKind::Row,
+            cells: (0..size).map(|x| xy(x, y)).collect(),
+        });
+    }
+    for x in 0..size {
+        regions.push(Region {
+            kind: RegionKind::Column,
+            cells: (0..size).map(|y| xy(x, y)).collect(),
+        });
+    }
+    regions
+}
+
+// Region files describe boards with diagonals and/or irregular (jigsaw) boxes.
+// Rows and columns are always cleared. Other lines:
+// `diagonals` - both diagonals are cleared too,
+// a line of box labels for each row - cells with the same label form a box (`.` - no box).
+// Without the box labels the board is divided into square boxes of `box_size`.
+// Empty lines and lines starting with `//` are skipped.
+pub fn parse_regions(text: &str, size: i32, box_size: i32) -> Result<Geometry, Error> {
+    let mut geometry = Geometry::new(size, box_size)?;
+    let mut diagonals = false;
+    let mut box_rows = vec![];
+    for line in text.lines().map(|line| line.trim()) {
+        if line.is_empty() || line.starts_with("//") {
+            continue;
+        }
+        if line == "diagonals" {
+            diagonals = true;
+        } else {
+            box_rows.push(line.chars().collect::<Vec<_>>());
+        }
+    }
+
+    if !box_rows.is_empty() {
+        if box_rows.len() != size as usize {
+            return Err(Error::Parse(format!("boxes should have {} rows, found {}", size, box_rows.len())));
+        }
+
+        geometry.box_size = 0;
+        geometry.regions.retain(|region| region.kind != RegionKind::Box);
+        let mut labels: Vec<char> = vec![];
+        for (y, row) in box_rows.iter().enumerate() {
+            if row.len() != size as usize {
+                return Err(Error::Parse(format!("box row {} should have {} cells", y + 1, size)));
+            }
+
+            for (x, &label) in row.iter().enumerate() {
+                if label == '.' {
+                    continue;
+                }
+                let cell = xy(x as i32, y as i32);
+                match labels.iter().position(|&l| l == label) {
+                    Some(i) => geometry.regions[2 * size as usize + i].cells.push(cell),
+                    None => {
+                        labels.push(label);
+                        geometry.regions.push(Region {
+                            kind: RegionKind::Box,
+                            cells: vec![cell],
+                        });
+                    }
+                }
+            }
+        }
+    }
+
+    if diagonals {
+        geometry.regions.push(Region {
+            kind: RegionKind::Diagonal,
+            cells: (0..size).map(|i| xy(i, i)).collect(),
+        });
+        geometry.regions.push(Region {
+            kind: RegionKind::Diagonal,
+            cells: (0..size).map(|i| xy(size - 1 - i, i)).collect(),
+        });
+    }
+
+    geometry.masks = masks(&geometry.regions);
+    Ok(geometry)
+}
+
+pub fn load_regions(path: &str, size: i32, box_size: i32) -> Result<Geometry, Error> {
+    let text = std::fs::read_to_string(path)
+        .map_err(|e| Error::Io(format!("can't read regions file {}: {}", path, e)))?;
+    parse_regions(&text, size, box_size)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_standard_regions() {
+        // 9 rows, 9 columns and 9 boxes
+        let geometry = Geometry::standard();
+        assert_eq!(geometry.regions.len(), 27);
+        assert!(geometry.regions.iter().all(|region| region.cells.len() == 9));
+    }
+
+    #[test]
+    fn test_board_without_boxes() {
+        let geometry = Geometry::new(8, 0).unwrap();
+        assert_eq!(geometry.regions.len(), 16);
+        assert!(geometry.regions.iter().all(|region| region.cells.len() == 8));
+    }
+
+    #[test]
+    fn test_big_board() {
+        let geometry = Geometry::new(16, 4).unwrap();
+        assert_eq!(geometry.regions.len(), 48);
+        assert!(geometry.regions.iter().all(|region| region.cells.len() == 16));
+    }
+
+    #[test]
+    fn test_invalid_geometry() {
+        assert!(Geometry::new(0, 0).is_err());
+        assert!(Geometry::new(MAX_BOARD_SIZE + 1, 0).is_err());
+        assert!(Geometry::new(9, 2).is_err());
+    }
+
+    #[test]
+    fn test_parse_diagonals() {
+        let geometry = parse_regions("// both diagonals\ndiagonals", 9, 3).unwrap();
+        assert_eq!(geometry.regions.len(), 29);
+        let diagonals = geometry
+            .regions
+            .iter()
+            .filter(|region| region.kind == RegionKind::Diagonal)
+            .collect::<Vec<_>>();
+        assert_eq!(diagonals.len(), 2);
+        assert!(diagonals[0].cells.contains(&xy(4, 4)));
+        assert!(diagonals[1].cells.contains(&xy(8, 0)));
+    }
+
+    #[test]
+    fn test_parse_jigsaw_boxes() {
+        let geometry = parse_regions("aabb\nacbb\naccd\n.ddd", 4, 2).unwrap();
+        assert_eq!(geometry.box_size, 0);
+
+        let boxes = geometry
+            .regions
+            .iter()
+            .filter(|region| region.kind == RegionKind::Box)
+            .map(|region| region.cells.len())
+            .collect::<Vec<_>>();
+        assert_eq!(boxes, vec![4, 4, 3, 4]);
+    }
+
+    #[test]
+    fn test_region_masks() {
+        for geometry in [Geometry::standard(), &parse_regions("aabb\nacbb\naccd\n.ddd\ndiagonals", 4, 2).unwrap()] {
+            assert_eq!(geometry.region_masks().len(), geometry.regions.len());
+            for (mask, region) in geometry.region_masks().iter().zip(&geometry.regions) {
+                assert_eq!(mask.iter().map(|row| row.count_ones() as usize).sum::<usize>(), region.cells.len());
+                assert!(region.cells.iter().all(|pos| mask[pos.y as usize] >> pos.x & 1 != 0));
+            }
+        }
+    }
+
+    #[test]
+    fn test_parse_invalid_regions() {
+        assert!(parse_regions("aabb\naabb", 4, 2).is_err());
+        assert!(parse_regions("aabb\naabb\nccdd\nccd", 4, 2).is_err());
+        assert!(parse_regions("diagonals", 9, 2).is_err());
+    }
+}
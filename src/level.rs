@@ -0,0 +1,111 @@
+use crate::brick::xy;
+use crate::error::Error;
+use crate::geometry::Geometry;
+use crate::{Board, Cell};
+
+// Levels are text files with a line of cells for each row, using the same characters as `print_board`:
+// `.` - empty, `X` - filled, `#` - stone, `@` - double stone.
+// Empty lines and lines starting with `//` are skipped.
+pub fn parse_level(text: &str, geometry: &'static Geometry) -> Result<Board, Error> {
+    let size = geometry.size as usize;
+    let rows = text
+        .lines()
+        .map(|line| line.trim())
+        .filter(|line| !line.is_empty() && !line.starts_with("//"))
+        .collect::<Vec<_>>();
+    if rows.len() != size {
+        return Err(Error::Parse(format!("level should have {} rows, found {}", size, rows.len())));
+    }
+
+    let mut board = Board::new(geometry);
+    for (y, row) in rows.iter().enumerate() {
+        let cells = row.chars().collect::<Vec<_>>();
+        if cells.len() != size {
+            return Err(Error::Parse(format!("row {} should have {} cells: `{}`", y + 1, size, row)));
+        }
+
+        for (x, c) in cells.iter().enumerate() {
+            let cell =
+                Cell::from_symbol(*c).ok_or_else(|| Error::Parse(format!("unknown cell `{}` in row {}", c, y + 1)))?;
+            board.set(xy(x as i32, y as i32), cell);
+        }
+    }
+
+    Ok(board)
+}
+
+pub fn load_level(path: &str, geometry: &'static Geometry) -> Result<Board, Error> {
+    let text = std::fs::read_to_string(path)
+        .map_err(|e| Error::Io(format!("can't read level file {}: {}", path, e)))?;
+    parse_level(&text, geometry)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing;
+    use proptest::prelude::*;
+
+    #[test]
+    fn test_parse_level() {
+        let board = parse_level(
+            "// a comment
+            X........
+            .#.......
+            ..@......
+
+            .........
+            .........
+            .........
+            .........
+            .........
+            ........X",
+            Geometry::standard(),
+        )
+        .unwrap();
+
+        assert_eq!(board.get(xy(0, 0)), Cell::Filled);
+        assert_eq!(board.get(xy(1, 1)), Cell::Stone);
+        assert_eq!(board.get(xy(2, 2)), Cell::DoubleStone);
+        assert_eq!(board.get(xy(8, 8)), Cell::Filled);
+        assert_eq!(board.get(xy(1, 0)), Cell::Empty);
+    }
+
+    #[test]
+    fn test_parse_invalid_level() {
+        let geometry = Geometry::standard();
+        assert!(parse_level("", geometry).is_err());
+        assert!(parse_level(&".........\n".repeat(8), geometry).is_err());
+        assert!(parse_level(&"........\n".repeat(9), geometry).is_err());
+        assert!(parse_level(&"........?\n".repeat(9), geometry).is_err());
+        assert!(matches!(parse_level("", geometry), Err(Error::Parse(_))));
+        assert!(matches!(load_level("levels/missing.txt", geometry), Err(Error::Io(_))));
+    }
+
+    #[test]
+    fn test_parse_level_of_other_size() {
+        let geometry = Geometry::new(4, 2).unwrap().leak();
+        let board = parse_level("#...\n....\n....\n...X", geometry).unwrap();
+        assert_eq!(board.size(), 4);
+        assert_eq!(board.get(xy(0, 0)), Cell::Stone);
+        assert_eq!(board.get(xy(3, 3)), Cell::Filled);
+
+        assert!(parse_level(&".........\n".repeat(9), geometry).is_err());
+    }
+
+    proptest! {
+        #[test]
+        fn test_printed_board_parses_back(board in testing::board()) {
+            let text = board
+                .rows()
+                .map(|row| row.iter().map(|cell| cell.symbol()).collect::<String>() + "\n")
+                .collect::<String>();
+            prop_assert_eq!(parse_level(&text, board.geometry()), Ok(board));
+        }
+
+        #[test]
+        fn test_parse_level_never_panics(text in "[.X#@?\n/ ]{0,100}") {
+            let _ = parse_level(&text, Geometry::standard());
+        }
+    }
+}
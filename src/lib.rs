@@ -1,4 +1,229 @@
-pub type Board = [[bool; 9]; 9];
+use brick::{xy, XY};
+use geometry::{Geometry, Region, RegionKind, MAX_BOARD_SIZE};
+use std::hash::{Hash, Hasher};
 
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Hash, Default)]
+pub enum Cell {
+    #[default]
+    Empty,
+    Filled,
+    // stays on the board when its row/column/block is cleared
+    Stone,
+    // turns into a regular filled cell when cleared for the first time
+    DoubleStone,
+}
+
+impl Cell {
+    pub fn is_filled(self) -> bool {
+        self != Cell::Empty
+    }
+
+    // what is left of the cell after clearing its row/column/block
+    pub fn cleared(self) -> Cell {
+        match self {
+            Cell::Empty | Cell::Filled => Cell::Empty,
+            Cell::Stone => Cell::Stone,
+            Cell::DoubleStone => Cell::Filled,
+        }
+    }
+
+    // characters used for displaying boards, in level files and in the bot protocols
+    pub fn symbol(self) -> char {
+        match self {
+            Cell::Empty => '.',
+            Cell::Filled => 'X',
+            Cell::Stone => '#',
+            Cell::DoubleStone => '@',
+        }
+    }
+
+    pub fn from_symbol(symbol: char) -> Option<Cell> {
+        match symbol {
+            '.' => Some(Cell::Empty),
+            'X' => Some(Cell::Filled),
+            '#' => Some(Cell::Stone),
+            '@' => Some(Cell::DoubleStone),
+            _ => None,
+        }
+    }
+}
+
+const MAX_CELLS: usize = (MAX_BOARD_SIZE * MAX_BOARD_SIZE) as usize;
+
+// Cells as bit masks of rows - bit x of `rows[y]` is the cell x,y. Used for bricks too.
+pub type Occupancy = [u16; MAX_BOARD_SIZE as usize];
+
+// Cells are accessed with their coordinates - `board.get(xy(x, y))` - never with bare indexes,
+// so rows and columns can't be mixed up.
+#[derive(Copy, Clone, Debug)]
+pub struct Board {
+    geometry: &'static Geometry,
+    // row by row
+    cells: [Cell; MAX_CELLS],
+}
+
+impl Board {
+    pub fn new(geometry: &'static Geometry) -> Board {
+        Board {
+            geometry,
+            cells: [Cell::Empty; MAX_CELLS],
+        }
+    }
+
+    pub fn geometry(&self) -> &'static Geometry {
+        self.geometry
+    }
+
+    pub fn size(&self) -> i32 {
+        self.geometry.size
+    }
+
+    pub fn contains(&self, pos: XY) -> bool {
+        pos.x >= 0 && pos.y >= 0 && pos.x < self.size() && pos.y < self.size()
+    }
+
+    fn index(&self, pos: XY) -> usize {
+        assert!(self.contains(pos), "{:?} is off the board", pos);
+        (pos.y * self.size() + pos.x) as usize
+    }
+
+    // panics for positions off the board - check them with `contains`
+    pub fn get(&self, pos: XY) -> Cell {
+        self.cells[self.index(pos)]
+    }
+
+    pub fn set(&mut self, pos: XY, cell: Cell) {
+        let i = self.index(pos);
+        self.cells[i] = cell;
+    }
+
+    // filled cells as bit masks of rows, for testing many placements of a brick at once
+    pub fn occupancy(&self) -> Occupancy {
+        let mut occupancy = Occupancy::default();
+        for (y, row) in self.rows().enumerate() {
+            for (x, cell) in row.iter().enumerate() {
+                if cell.is_filled() {
+                    occupancy[y] |= 1 << x;
+                }
+            }
+        }
+        occupancy
+    }
+
+    // all the positions, row by row
+    pub fn positions(&self) -> impl Iterator<Item = XY> {
+        let size = self.size();
+        (0..size).flat_map(move |y| (0..size).map(move |x| xy(x, y)))
+    }
+
+    // all the cells with their positions, row by row
+    pub fn cells(&self) -> impl Iterator<Item = (XY, Cell)> + '_ {
+        self.positions().zip(self.rows().flatten().copied())
+    }
+
+    pub fn rows(&self) -> impl Iterator<Item = &[Cell]> {
+        self.cells[..self.size() as usize * self.size() as usize].chunks(self.size() as usize)
+    }
+
+    pub fn columns(&self) -> impl Iterator<Item = impl Iterator<Item = Cell> + '_> + '_ {
+        let size = self.size() as usize;
+        (0..size).map(move |x| self.cells[x..].iter().step_by(size).take(size).copied())
+    }
+
+    // boxes of the geometry (square or jigsaw ones)
+    pub fn boxes(&self) -> impl Iterator<Item = impl Iterator<Item = Cell> + '_> + '_ {
+        self.geometry
+            .regions
+            .iter()
+            .filter(|region| region.kind == RegionKind::Box)
+            .map(move |region| self.region(region))
+    }
+
+    pub fn region<'a>(&'a self, region: &'a Region) -> impl Iterator<Item = Cell> + 'a {
+        region.cells.iter().map(move |&pos| self.get(pos))
+    }
+}
+
+// 9x9 board with 3x3 boxes
+impl Default for Board {
+    fn default() -> Self {
+        Board::new(Geometry::standard())
+    }
+}
+
+impl PartialEq for Board {
+    fn eq(&self, other: &Self) -> bool {
+        (std::ptr::eq(self.geometry, other.geometry) || self.geometry == other.geometry)
+            && self.cells[..] == other.cells[..]
+    }
+}
+
+impl Eq for Board {}
+
+impl Hash for Board {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        self.cells.hash(state);
+    }
+}
+
+pub mod ai;
+pub mod board;
+pub mod dataset;
+pub mod dealer;
+pub mod env;
+pub mod error;
+pub mod feasibility;
+pub mod game;
+pub mod geometry;
+pub mod level;
+pub mod log;
+pub mod metrics;
+pub mod mlp;
+pub mod notation;
+pub mod referee;
 pub mod resolve;
-pub mod brick;
\ No newline at end of file
+pub mod search;
+pub mod server;
+#[cfg(test)]
+mod testing;
+pub mod tournament;
+pub mod brick;
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_get_and_set() {
+        let mut board = Board::default();
+        board.set(xy(2, 0), Cell::Stone);
+        assert_eq!(board.get(xy(2, 0)), Cell::Stone);
+        // the first row, the third column
+        assert_eq!(board.rows().next().unwrap()[2], Cell::Stone);
+        assert_eq!(board.columns().nth(2).unwrap().next(), Some(Cell::Stone));
+        assert_eq!(board.get(xy(0, 2)), Cell::Empty);
+
+        assert!(board.contains(xy(8, 8)));
+        assert!(!board.contains(xy(9, 0)));
+        assert!(!board.contains(xy(0, -1)));
+    }
+
+    #[test]
+    fn test_iterators() {
+        let mut board = Board::default();
+        board.set(xy(4, 1), Cell::Filled);
+
+        assert_eq!(board.positions().count(), 81);
+        assert_eq!(board.positions().nth(9), Some(xy(0, 1)));
+        assert_eq!(board.cells().filter(|(_, cell)| cell.is_filled()).collect::<Vec<_>>(), vec![(xy(4, 1), Cell::Filled)]);
+
+        let filled_per_box = board
+            .boxes()
+            .map(|cells| cells.filter(|cell| cell.is_filled()).count())
+            .collect::<Vec<_>>();
+        assert_eq!(filled_per_box, vec![0, 1, 0, 0, 0, 0, 0, 0, 0]);
+
+        let occupancy = board.occupancy();
+        assert_eq!(occupancy[1], 1 << 4);
+        assert_eq!(occupancy.iter().filter(|&&row| row != 0).count(), 1);
+    }
+}
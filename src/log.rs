@@ -0,0 +1,180 @@
+use std::io::Write;
+use std::sync::atomic::{AtomicU8, Ordering};
+
+// Messages go to stderr, so they don't mix with the results printed to stdout. Every target has
+// its own level; a message is written if its level is at most the target's level. Disabled
+// messages cost a single atomic load - the arguments aren't even formatted.
+#[derive(Copy, Clone, Debug, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Level {
+    Off,
+    Error,
+    Warn,
+    Info,
+    Debug,
+    Trace,
+}
+
+pub const LEVELS: [Level; 6] = [Level::Off, Level::Error, Level::Warn, Level::Info, Level::Debug, Level::Trace];
+
+impl Level {
+    pub fn name(self) -> &'static str {
+        match self {
+            Level::Off => "off",
+            Level::Error => "error",
+            Level::Warn => "warn",
+            Level::Info => "info",
+            Level::Debug => "debug",
+            Level::Trace => "trace",
+        }
+    }
+
+    pub fn from_name(name: &str) -> Option<Level> {
+        LEVELS.iter().copied().find(|level| level.name() == name)
+    }
+}
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum Target {
+    // rules of the game - moves, dealing bricks, clearing regions
+    Engine,
+    // the bot's decisions
+    Search,
+    // the command line interface
+    Ui,
+}
+
+pub const TARGETS: [Target; 3] = [Target::Engine, Target::Search, Target::Ui];
+
+impl Target {
+    pub fn name(self) -> &'static str {
+        match self {
+            Target::Engine => "engine",
+            Target::Search => "search",
+            Target::Ui => "ui",
+        }
+    }
+
+    pub fn from_name(name: &str) -> Option<Target> {
+        TARGETS.iter().copied().find(|target| target.name() == name)
+    }
+}
+
+const DEFAULT_LEVEL: Level = Level::Info;
+
+static LEVEL_VALUES: [AtomicU8; TARGETS.len()] = [const { AtomicU8::new(DEFAULT_LEVEL as u8) }; TARGETS.len()];
+
+pub fn set_level(target: Target, level: Level) {
+    LEVEL_VALUES[target as usize].store(level as u8, Ordering::Relaxed);
+}
+
+pub fn level(target: Target) -> Level {
+    LEVELS[LEVEL_VALUES[target as usize].load(Ordering::Relaxed) as usize]
+}
+
+#[inline]
+pub fn enabled(target: Target, level: Level) -> bool {
+    level != Level::Off && level as u8 <= LEVEL_VALUES[target as usize].load(Ordering::Relaxed)
+}
+
+// Sets the levels from a spec like `debug` (all the targets) or `info,search=trace` (all the
+// targets, then the listed ones). Nothing is changed if the spec is invalid.
+pub fn configure(spec: &str) -> Result<(), String> {
+    let mut levels = TARGETS.map(level);
+    for part in spec.split(',') {
+        let (target, level_name) = match part.split_once('=') {
+            Some((target, level_name)) => (Some(target), level_name),
+            None => (None, part),
+        };
+        let level = Level::from_name(level_name).ok_or_else(|| format!("unknown log level: {}", level_name))?;
+        match target {
+            Some(name) => {
+                let target = Target::from_name(name).ok_or_else(|| format!("unknown log target: {}", name))?;
+                levels[target as usize] = level;
+            }
+            None => levels = [level; TARGETS.len()],
+        }
+    }
+
+    for (&target, &level) in TARGETS.iter().zip(&levels) {
+        set_level(target, level);
+    }
+    Ok(())
+}
+
+// used by the macros - check `enabled` first
+pub fn write(target: Target, level: Level, message: std::fmt::Arguments) {
+    let _ = writeln!(std::io::stderr(), "[{} {}] {}", target.name(), level.name(), message);
+}
+
+// log!(Target::Search, Level::Debug, "best move: {}", m)
+#[macro_export]
+macro_rules! log {
+    ($target:expr, $level:expr, $($arg:tt)+) => {{
+        let (target, level) = ($target, $level);
+        if $crate::log::enabled(target, level) {
+            $crate::log::write(target, level, format_args!($($arg)+));
+        }
+    }};
+}
+
+#[macro_export]
+macro_rules! error {
+    ($target:expr, $($arg:tt)+) => { $crate::log!($target, $crate::log::Level::Error, $($arg)+) };
+}
+
+#[macro_export]
+macro_rules! warn {
+    ($target:expr, $($arg:tt)+) => { $crate::log!($target, $crate::log::Level::Warn, $($arg)+) };
+}
+
+#[macro_export]
+macro_rules! info {
+    ($target:expr, $($arg:tt)+) => { $crate::log!($target, $crate::log::Level::Info, $($arg)+) };
+}
+
+#[macro_export]
+macro_rules! debug {
+    ($target:expr, $($arg:tt)+) => { $crate::log!($target, $crate::log::Level::Debug, $($arg)+) };
+}
+
+#[macro_export]
+macro_rules! trace {
+    ($target:expr, $($arg:tt)+) => { $crate::log!($target, $crate::log::Level::Trace, $($arg)+) };
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // the levels are global, so everything touching them is in a single test
+    #[test]
+    fn test_configure() {
+        configure("warn,search=trace").unwrap();
+        assert_eq!(level(Target::Engine), Level::Warn);
+        assert_eq!(level(Target::Search), Level::Trace);
+        assert!(enabled(Target::Engine, Level::Error));
+        assert!(!enabled(Target::Engine, Level::Info));
+        assert!(enabled(Target::Search, Level::Trace));
+
+        assert!(configure("search=loud").is_err());
+        assert!(configure("board=info").is_err());
+        assert_eq!(level(Target::Search), Level::Trace);
+
+        configure("off").unwrap();
+        assert!(!enabled(Target::Ui, Level::Error));
+        assert!(!enabled(Target::Ui, Level::Off));
+        configure(DEFAULT_LEVEL.name()).unwrap();
+    }
+
+    #[test]
+    fn test_macros_skip_disabled_messages() {
+        let mut formatted = false;
+        let mut argument = || {
+            formatted = true;
+            1
+        };
+        // `Off` is never enabled, whatever the levels are
+        log!(Target::Engine, Level::Off, "{}", argument());
+        assert!(!formatted);
+    }
+}
@@ -1,462 +1,1034 @@
-use roku_doku::brick::{print_brick, random_brick, xy, Brick, XY};
-use roku_doku::resolve::resolve_board;
+use roku_doku::ai::{ai_action_with, ai_submove_with, greedy_action, random_action, ActionScore, Evaluator};
+use roku_doku::board::{print_board, print_board_with_highlight};
+use roku_doku::brick::{print_brick, print_cells, xy, BrickId, XY};
+use roku_doku::dealer::{AdversarialDealer, BrickQueue, Dealer, DealerFactory, RandomDealer, RecordingDealer};
+use roku_doku::dealer::{ScriptedDealer, SeededDealer, MAX_ADVERSARIAL_COUNT, MAX_PREVIEW};
+use roku_doku::game::{can_hold, get_possible_moves, is_game_over, perform_action};
+use roku_doku::game::{Action, GameState, Move, PossibleMove};
+use roku_doku::game::{RefillPolicy, Rules};
+use roku_doku::level::load_level;
+use roku_doku::log::{self, Level, Target};
+use roku_doku::geometry::{load_regions, Geometry, REGION_KINDS};
+use roku_doku::notation::{format_action, format_move, parse_action};
+use roku_doku::dataset::{load_positions, write_game, write_header, Sample};
+use roku_doku::error::Error;
+use roku_doku::metrics::{self, Metrics};
+use roku_doku::mlp::{board_features, Mlp, POINTS_SCALE};
+use roku_doku::referee::ExternalBot;
+use roku_doku::search::Parallelism;
+use roku_doku::server::{serve, ServerConfig};
+use roku_doku::tournament::{head_to_head, ratings, summary};
 use roku_doku::Board;
-use rand::{thread_rng, Rng};
-use std::time::Instant;
+use roku_doku::{debug, info};
+use rand::rngs::StdRng;
+use rand::SeedableRng;
 use rayon::prelude::*;
-use global_counter::generic::Counter;
-use global_counter::primitive::exact::CounterI32;
+use serde_json::json;
+use std::io::Write;
+use std::time::{Duration, Instant};
 
-static PUT_BRICK_COUNTER : CounterI32 = CounterI32::new(0);
-static CAN_PUT_BRICK_COUNTER : CounterI32 = CounterI32::new(0);
-static POSSIBLE_MOVES_COUNTER : CounterI32 = CounterI32::new(0);
+fn print_bricks(bricks: &[BrickId]) {
+    println!();
+
+    // let's display 3 bricks as 1 big brick
+    let mut joined_brick_offsets = vec![];
+    let mut x_offset = 0;
+    for brick in bricks.iter().map(|id| id.brick()) {
+        joined_brick_offsets.extend(brick.offsets().iter().map(|v| xy(v.x + x_offset, v.y)));
+        x_offset += brick.width() + 2;
+    }
 
-#[derive(Debug, Clone)]
-struct GameState {
-    board: Board,
-    available_bricks: Vec<Brick>,
-    points: i32,
-    last_move_was_match: bool,
+    print_cells(&joined_brick_offsets);
 }
 
-impl GameState {
-    fn new() -> GameState {
-        GameState {
-            board: Board::default(),
-            points: 0,
-            last_move_was_match: false,
-            available_bricks: vec![],
+fn print_game_state(game_state: &GameState) {
+    println!("\ncurrent points: {}\n", game_state.points);
+    print_board(&game_state.board);
+    print_bricks(&game_state.available_bricks);
+    if !game_state.upcoming_bricks.is_empty() {
+        println!("\nnext bricks:");
+        print_bricks(&game_state.upcoming_bricks);
+    }
+    if game_state.rules.hold {
+        println!("\nheld brick:");
+        match &game_state.held_brick {
+            Some(brick) => print_brick(brick.brick()),
+            None => println!("-"),
         }
     }
 }
 
-fn print_board(board: &Board) {
-    let mut row_counter = 0;
-    println!("  abc def ghi");
-    for row in board {
-        if row_counter % 3 == 0 {
-            println!(" -------------");
-        }
+// only the kinds of regions present on the board are listed
+fn print_cleared_regions(game_state: &GameState) {
+    let regions = &game_state.board.geometry().regions;
+    let cleared = REGION_KINDS
+        .iter()
+        .filter(|&&kind| regions.iter().any(|region| region.kind == kind))
+        .map(|&kind| format!("{}: {}", kind.name(), game_state.cleared_regions[kind as usize]))
+        .collect::<Vec<_>>();
+    println!(" cleared {}", cleared.join(", "));
+}
 
-        print!("{}", row_counter + 1);
-        let mut col_counter = 0;
-        for cell in row {
-            if col_counter % 3 == 0 {
-                print!("|");
-            }
-            let c = if *cell { "X" } else { "." };
-            print!("{}", c);
-            col_counter = col_counter + 1;
-        }
-        println!("|{}", row_counter + 1);
+fn read_line() -> String {
+    let mut input_text = String::new();
+    let bytes_read = std::io::stdin()
+        .read_line(&mut input_text)
+        .expect("failed to read from stdin");
+    if bytes_read == 0 {
+        println!("end of input - leaving the game");
+        std::process::exit(0);
+    }
+    input_text
+}
 
-        row_counter = row_counter + 1;
+fn print_hint(game_state: &GameState, evaluator: &Evaluator) {
+    let hint = match ai_action_with(game_state, evaluator, Parallelism::Root) {
+        Ok(hint) => hint,
+        Err(e) => {
+            println!("no hint: {}", e);
+            return;
+        }
+    };
+    println!(
+        "hint: `{}` (expected score: {})",
+        format_action(&hint.action),
+        hint.score
+    );
+    if let Action::Place(m) = &hint.action {
+        print_board_with_highlight(&game_state.board, &brick_cells(game_state, m));
     }
-    println!(" -------------");
-    println!("  abc def ghi");
 }
 
-fn print_bricks(bricks: &Vec<Brick>) {
-    println!();
+fn read_user_action(game_state: &GameState, evaluator: &Evaluator) -> Action {
+    loop {
+        if can_hold(game_state) {
+            println!("\ntype a move in form `brick_no position` - e.g. `3 d4`, `hold brick_no` - e.g. `hold 2`, or `hint`");
+        } else {
+            println!("\ntype a move in form `brick_no position` - e.g. `3 d4`, or `hint`");
+        }
+        let input_text = read_line();
+        if input_text.trim() == "hint" {
+            print_hint(game_state, evaluator);
+            continue;
+        }
 
-    // let's display 3 bricks as 1 big brick
-    let mut joined_brick_offsets = vec![];
-    let mut x_offset = 0;
-    for brick in bricks {
-        joined_brick_offsets.extend(brick.offsets.iter().map(|v| xy(v.x + x_offset, v.y)));
-        x_offset = x_offset + brick.offsets.iter().max_by_key(|v| v.x).unwrap().x + 3;
+        match parse_action(input_text.trim(), game_state) {
+            Ok(action) => return action,
+            Err(e) => println!("{}", e),
+        }
     }
+}
 
-    print_brick(&Brick {
-        offsets: joined_brick_offsets,
-    });
+// cells of the board covered by the brick after the move
+fn brick_cells(game_state: &GameState, m: &Move) -> Vec<XY> {
+    game_state.available_bricks[m.brick_index as usize]
+        .brick()
+        .offsets()
+        .iter()
+        .map(|v| xy(m.pos.x + v.x, m.pos.y + v.y))
+        .collect()
 }
 
-fn print_game_state(game_state: &GameState) {
-    println!("\ncurrent points: {}\n", game_state.points);
-    print_board(&game_state.board);
-    print_bricks(&game_state.available_bricks);
+struct PlayOptions {
+    // warn when the chosen move scores that many points less than the bot's best move
+    coach_threshold: Option<i32>,
+}
+
+struct GameOptions {
+    rules: Rules,
+    // number of upcoming bricks shown to the player
+    preview: usize,
+    // every game gets its own dealer (see `new_dealer`), so games can be played in parallel
+    dealer: DealerFactory,
+    size: i32,
+    // if not set, boxes are used only if the board size is a square number
+    box_size: Option<i32>,
+    level_path: Option<String>,
+    regions_path: Option<String>,
+    // board every game starts with - set up by `prepare` once all the options are parsed
+    start_board: Board,
+    // used by the bot, also for hints and coaching
+    evaluator: Evaluator,
+    metrics: MetricsOutput,
+    // size of rayon's thread pool, one thread per core if not set
+    threads: Option<usize>,
+    // commands playing many games play them in parallel, each search on a single thread
+    // (instead of searching the moves in parallel)
+    parallel_games: bool,
 }
 
-fn can_put_brick(board: &Board, brick: &Brick, pos: &XY) -> bool {
-    CAN_PUT_BRICK_COUNTER.inc();
+impl GameOptions {
+    fn prepare(&mut self) -> Result<(), String> {
+        let dealer = self.new_dealer();
+        if self.preview > 0 && !dealer.supports_preview() {
+            return Err("this dealer picks the bricks against the current board, it can't be used with a preview".to_string());
+        }
+        if self.rules.hand_size > dealer.max_count() {
+            return Err(format!("this dealer deals hands of at most {} bricks", dealer.max_count()));
+        }
 
-    assert!(pos.x >= 0);
-    assert!(pos.y >= 0);
+        if let Some(threads) = self.threads {
+            rayon::ThreadPoolBuilder::new()
+                .num_threads(threads)
+                .build_global()
+                .map_err(|e| format!("can't start {} threads: {}", threads, e))?;
+        }
+
+        let box_size = self.box_size.unwrap_or_else(|| {
+            let root = (self.size as f64).sqrt() as i32;
+            if root * root == self.size { root } else { 0 }
+        });
+        let geometry = if let Some(path) = &self.regions_path {
+            load_regions(path, self.size, box_size).map_err(|e| e.to_string())?.leak()
+        } else if self.size == 9 && box_size == 3 {
+            Geometry::standard()
+        } else {
+            Geometry::new(self.size, box_size).map_err(|e| e.to_string())?.leak()
+        };
 
-    let min_x = brick.offsets.iter().min_by_key(|v| v.x).unwrap().x;
-    assert!(min_x >= 0);
+        self.start_board = match &self.level_path {
+            Some(path) => load_level(path, geometry).map_err(|e| e.to_string())?,
+            None => Board::new(geometry),
+        };
 
-    let min_y = brick.offsets.iter().min_by_key(|v| v.y).unwrap().y;
-    assert!(min_y >= 0);
+        self.evaluator.check(&self.start_board).map_err(|e| e.to_string())
+    }
 
-    let max_x = brick.offsets.iter().max_by_key(|v| v.x).unwrap().x;
-    let max_y = brick.offsets.iter().max_by_key(|v| v.y).unwrap().y;
-    
-    if pos.x + max_x > 8 || pos.y + max_y > 8 {
-        return false;
+    // searching like the bot, if the dealer searches at all
+    fn new_dealer(&self) -> Box<dyn Dealer> {
+        (self.dealer)(&self.evaluator, self.parallelism())
     }
 
-    for offset in &brick.offsets {
-        if board[(pos.y + offset.y) as usize][(pos.x + offset.x) as usize] {
-            return false;
+    // how the bot searches while playing many games
+    fn parallelism(&self) -> Parallelism {
+        if self.parallel_games {
+            Parallelism::Off
+        } else {
+            Parallelism::Root
         }
     }
 
-    return true;
+    fn new_game(&self) -> GameState {
+        let mut game_state = GameState::with_rules(self.rules);
+        game_state.board = self.start_board;
+        game_state
+    }
 }
 
-fn put_brick(board: &Board, brick: &Brick, pos: &XY) -> Board {
-    PUT_BRICK_COUNTER.inc();
-
-    // assert!(can_put_brick(&board, &brick, &pos));
-    // DEBUG
-    if !can_put_brick(&board, &brick, &pos){
-        println!("FATAL - PUTTING BRICK IN INVALID PLACE: PRINTING STATE BEFORE LEAVING");
-        print_board(&board);
-        print_brick(&brick);
-        dbg!(pos);
-        panic!();
+// returns false if the player decided to take the move back
+fn coach_move(game_state: &GameState, user_action: &Action, options: &PlayOptions, evaluator: &Evaluator) -> bool {
+    let threshold = match options.coach_threshold {
+        Some(threshold) => threshold,
+        None => return true,
+    };
+    // only placing bricks is scored by the search
+    let user_move = match user_action {
+        Action::Place(m) => m,
+        Action::Hold { .. } => return true,
+    };
+
+    let chosen = ai_submove_with(
+        game_state,
+        &PossibleMove {
+            brick: game_state.available_bricks[user_move.brick_index as usize],
+            brick_index: user_move.brick_index,
+            pos: user_move.pos,
+        },
+        evaluator,
+        Parallelism::Root,
+    );
+    let (best, chosen) = match (ai_action_with(game_state, evaluator, Parallelism::Root), chosen) {
+        (Ok(best), Ok(chosen)) => (best, chosen),
+        // nothing to compare with
+        _ => return true,
+    };
+    if best.score - chosen.score <= threshold {
+        return true;
     }
 
-    let mut new_board = board.clone();
-    for offset in &brick.offsets {
-        new_board[(pos.y + offset.y) as usize][(pos.x + offset.x) as usize] = true;
+    println!(
+        "coach: your move scores {}, but `{}` scores {}",
+        chosen.score,
+        format_action(&best.action),
+        best.score
+    );
+    if let Action::Place(m) = &best.action {
+        print_board_with_highlight(&game_state.board, &brick_cells(game_state, m));
     }
-
-    new_board
+    println!("play `{}` anyway? [y/N]", format_move(user_move));
+    read_line().trim() == "y"
 }
 
-fn possible_moves(board: &Board, brick: &Brick) -> Vec<XY> {
-    POSSIBLE_MOVES_COUNTER.inc();
+fn play(options: &PlayOptions, game_options: &GameOptions) {
+    let mut dealer = game_options.new_dealer();
+    let mut queue = BrickQueue::new(game_options.preview);
+    let mut move_counter = 0;
+    let mut game_state = game_options.new_game();
+    loop {
+        queue.refill(&mut game_state, dealer.as_mut());
+
+        print_game_state(&game_state);
+
+        if is_game_over(&game_state) {
+            println!("game over!\n your score: {} (in {} moves)", game_state.points, &move_counter);
+            print_cleared_regions(&game_state);
+            return;
+        }
+
+        let user_action = read_user_action(&game_state, &game_options.evaluator);
+        if !coach_move(&game_state, &user_action, options, &game_options.evaluator) {
+            continue;
+        }
 
-    let mut ret = vec![];
-    for x in 0..9 {
-        for y in 0..9 {
-            let pos = xy(x, y);
-            if can_put_brick(board, brick, &pos) {
-                ret.push(pos);
+        match perform_action(&game_state, &user_action) {
+            Ok(next_state) => game_state = next_state,
+            Err(e) => {
+                println!("{}", e);
+                continue;
             }
         }
+        move_counter += 1;
     }
-
-    ret
 }
 
-#[derive(Debug)]
-struct Move {
-    // 0-based index
-    brick_index: i32,
-    // 0-based axes
-    pos: XY,
+struct RefereeOptions {
+    // shell command starting the external bot
+    bot_command: Option<String>,
+    time_limit: Duration,
+    games: usize,
 }
 
-fn read_user_move(game_state: &GameState) -> Move {
+// Plays a game until it's over or the player fails to choose a legal action in time.
+fn play_game(
+    mut game_state: GameState,
+    preview: usize,
+    dealer: &mut dyn Dealer,
+    mut player: impl FnMut(&GameState) -> Result<Action, String>,
+) -> (GameState, Option<String>) {
+    let mut queue = BrickQueue::new(preview);
     loop {
-        println!("\ntype a move in form `brick_no position` - e.g. `3 d4`");
-        let mut input_text = String::new();
-        std::io::stdin()
-            .read_line(&mut input_text)
-            .expect("failed to read from stdin");
-
-        let split = input_text.split(' ').collect::<Vec<_>>();
-        if split.len() != 2 {
-            println!("input should have 2 parts delimited with space - e.g. `0 d3`");
-            continue;
+        queue.refill(&mut game_state, dealer);
+        if is_game_over(&game_state) {
+            return (game_state, None);
         }
 
-        let first_val = split.get(0).unwrap().trim();
-        let brick_index = match first_val.parse::<i32>() {
-            Ok(i) => {
-                if i > 0 && i <= game_state.available_bricks.len() as i32 {
-                    i - 1 // translation from 1-based to 0-based indexing
-                } else {
-                    print!(
-                        "only bricks {:?} are available",
-                        0..game_state.available_bricks.len()
-                    );
-                    continue;
-                }
-            }
-            Err(..) => {
-                println!("first value should be an integer: {}", first_val);
-                continue;
-            }
-        };
-
-        let second_val = split.get(1).unwrap().trim();
-        if second_val.len() != 2 {
-            println!("second part of the input should contains a letter a-i followed by a digit 1-9 - e.g. `d3`");
-            continue;
+        let next_state = player(&game_state)
+            .and_then(|action| perform_action(&game_state, &action).map_err(|e| e.to_string()));
+        match next_state {
+            Ok(next_state) => game_state = next_state,
+            Err(e) => return (game_state, Some(e)),
         }
+    }
+}
 
-        let x_char = second_val.chars().nth(0).unwrap();
-        let x_letters = ['a', 'b', 'c', 'd', 'e', 'f', 'g', 'h', 'i'];
-        let x_index = x_letters.iter().position(|c| c == &x_char);
-        let x = match x_index {
-            Some(x) => x,
-            None => {
-                println!("second part of the input should contains a letter a-i followed by a digit 1-9 - e.g. `d3`");
-                continue;
+// The external bot plays each game first, then the built-in bot gets the same bricks.
+// A bot which makes an illegal move or runs out of time loses the game with the points scored so far.
+fn referee(options: &RefereeOptions, game_options: &GameOptions) {
+    let bot_command = match &options.bot_command {
+        Some(bot_command) => bot_command,
+        None => {
+            println!("referee needs a bot to play - see `--bot`");
+            return;
+        }
+    };
+
+    let mut bot_scores = vec![];
+    let mut ai_scores = vec![];
+    for game in 1..=options.games {
+        let mut bot = match ExternalBot::spawn(bot_command) {
+            Ok(bot) => bot,
+            Err(e) => {
+                println!("{}", e);
+                return;
             }
         };
+        let new_game = game_options.new_game();
+        let mut game_dealer = game_options.new_dealer();
+        let mut dealer = RecordingDealer::new(game_dealer.as_mut());
+        let (game_state, error) = play_game(new_game.clone(), game_options.preview, &mut dealer, |game_state| {
+            bot.request_action(game_state, options.time_limit).map_err(|e| e.to_string())
+        });
+        bot.finish(&game_state);
+        if let Some(e) = error {
+            println!("game {}: the bot lost - {}", game, e);
+        }
+        bot_scores.push(game_state.points);
+
+        let evaluator = &game_options.evaluator;
+        let mut dealer = ScriptedDealer::new(dealer.dealt, game_dealer.as_mut());
+        let (ai_game_state, _) = play_game(new_game, game_options.preview, &mut dealer, |game_state| {
+            ai_action_with(game_state, evaluator, Parallelism::Root)
+                .map(|best| best.action)
+                .map_err(|e| e.to_string())
+        });
+        ai_scores.push(ai_game_state.points);
+
+        println!(
+            "game {}: bot {}, ai_action {}",
+            game, game_state.points, ai_game_state.points
+        );
+    }
 
-        let y_char = second_val.chars().nth(1).unwrap();
-        let y = match y_char.to_string().parse::<i32>() {
-            Ok(i) => {
-                if i > 0 && i <= 9 {
-                    i - 1
-                } else {
-                    println!("second part of the input should contains a letter a-i followed by a digit 1-9 - e.g. `d3`");
-                    continue;
-                }
-            }
-            Err(..) => {
-                println!("second part of the input should contains a letter a-i followed by a digit 1-9 - e.g. `d3`");
-                continue;
-            }
-        };
+    println!(
+        "avg score: bot {}, ai_action {}",
+        bot_scores.iter().sum::<i32>() / bot_scores.len() as i32,
+        ai_scores.iter().sum::<i32>() / ai_scores.len() as i32
+    );
+}
 
-        let brick = game_state
-            .available_bricks
-            .get(brick_index as usize)
-            .unwrap();
-        let pos = xy(x as i32, y);
-        if !can_put_brick(&game_state.board, &brick, &pos) {
-            println!(
-                "the brick ({}) can't be put in the position you selected ({})",
-                &first_val, &second_val
-            );
-            continue;
-        }
+struct SelfPlayOptions {
+    games: usize,
+    output: String,
+}
 
-        return Move { brick_index, pos };
-    }
+// a game of the bot, with a sample for every position
+fn self_play_game(game_options: &GameOptions) -> (Vec<Sample>, i32) {
+    let mut samples = vec![];
+    let evaluator = &game_options.evaluator;
+    let mut dealer = game_options.new_dealer();
+    let (game_state, _) = play_game(game_options.new_game(), game_options.preview, dealer.as_mut(), |game_state| {
+        let best = ai_action_with(game_state, evaluator, game_options.parallelism()).map_err(|e| e.to_string())?;
+        samples.push(Sample {
+            game_state: game_state.clone(),
+            action: best.action.clone(),
+            score: best.score,
+        });
+        Ok(best.action)
+    });
+    (samples, game_state.points)
 }
 
-fn newly_filled_cells_count(new_board: &Board, old_board: &Board) -> i32 {
-    let mut ret = 0;
-    for x in 0..9 {
-        for y in 0..9 {
-            ret = ret
-                + if new_board[y][x] && !old_board[y][x] {
-                    1
-                } else {
-                    0
-                }
+// The bot plays games and every position is written with the bot's action, its search score
+// and the result of the game - see `src/dataset.rs` for the format.
+fn self_play(options: &SelfPlayOptions, game_options: &GameOptions) {
+    let file = match std::fs::File::create(&options.output) {
+        Ok(file) => file,
+        Err(e) => {
+            println!("can't create {}: {}", options.output, e);
+            return;
         }
+    };
+    let mut out = std::io::BufWriter::new(file);
+
+    let play = |game: usize| {
+        let (samples, points) = self_play_game(game_options);
+        println!("game {}: {} points in {} moves", game, points, samples.len());
+        (samples, points)
+    };
+    // parallel games are written once they are all over, in order
+    let games: Box<dyn Iterator<Item = (Vec<Sample>, i32)>> = if game_options.parallel_games {
+        Box::new((1..=options.games).into_par_iter().map(play).collect::<Vec<_>>().into_iter())
+    } else {
+        Box::new((1..=options.games).map(play))
+    };
+
+    let mut result = write_header(&mut out);
+    for (game, (samples, points)) in (1..).zip(games) {
+        result = result.and_then(|_| write_game(&mut out, game, &samples, points));
+        if result.is_err() {
+            break;
+        }
+    }
+
+    if let Err(e) = result.and_then(|_| out.flush()) {
+        println!("can't write {}: {}", options.output, e);
     }
+}
+
+type Strategy = Box<dyn Fn(&GameState, Parallelism) -> Result<ActionScore, Error> + Send + Sync>;
 
-    ret
+struct Entrant {
+    name: String,
+    strategy: Strategy,
+    // checked against the board before the games start
+    evaluator: Evaluator,
 }
 
-// Points:
-// 18 points per line
-// 1 point for each cell that is filled after current move, that wasn't filled before current move
-// if previous move was a line +9 points extra (streak)
+struct TournamentOptions {
+    entrants: Vec<Entrant>,
+    games: usize,
+    // games are played with bricks dealt from seeds `first_seed..first_seed + games`
+    first_seed: u64,
+}
 
-fn perform_move(game_state: &GameState, m: &Move) -> GameState {
-    assert!(m.brick_index >= 0);
-    assert!(m.brick_index < game_state.available_bricks.len() as i32);
+// `heuristic` (the default bot), `greedy`, `random` or `model:FILE` (the bot with a trained network)
+fn parse_strategy(name: &str) -> Result<Entrant, String> {
+    let mut evaluator = Evaluator::Heuristic;
+    let strategy: Strategy = match name {
+        "heuristic" => Box::new(|game_state: &GameState, parallelism| {
+            ai_action_with(game_state, &Evaluator::Heuristic, parallelism)
+        }),
+        "greedy" => Box::new(|game_state: &GameState, _| greedy_action(game_state)),
+        "random" => Box::new(|game_state: &GameState, _| random_action(game_state)),
+        _ => match name.strip_prefix("model:") {
+            Some(path) => {
+                evaluator = Evaluator::Mlp(Mlp::load(path).map_err(|e| e.to_string())?);
+                let evaluator = evaluator.clone();
+                Box::new(move |game_state: &GameState, parallelism| ai_action_with(game_state, &evaluator, parallelism))
+            }
+            None => return Err(format!("unknown strategy: {}", name)),
+        },
+    };
+    Ok(Entrant {
+        name: name.to_string(),
+        strategy,
+        evaluator,
+    })
+}
 
-    let mut available_bricks = game_state.available_bricks.clone();
-    let brick = available_bricks.remove(m.brick_index as usize);
-    let board = put_brick(&game_state.board, &brick, &m.pos);
-    let resolve_result = resolve_board(board);
-    let board = resolve_result.board;
+// Every strategy plays the same games - bricks are dealt from the same seeds regardless of the
+// `--dealer` option, so results can be compared game by game.
+fn tournament(options: &mut TournamentOptions, game_options: &GameOptions) {
+    if options.entrants.is_empty() {
+        options.entrants = ["heuristic", "greedy", "random"]
+            .iter()
+            .map(|name| parse_strategy(name).unwrap())
+            .collect();
+    }
+    for entrant in &options.entrants {
+        if let Err(e) = entrant.evaluator.check(&game_options.start_board) {
+            println!("{}: {}", entrant.name, e);
+            return;
+        }
+    }
 
-    let streak = game_state.last_move_was_match && resolve_result.blocks_removed > 0;
-    let streak_points = if streak { 9 } else { 0 };
-    let new_cells_points = newly_filled_cells_count(&board, &game_state.board);
-    let bonus_points = resolve_result.blocks_removed * 18 + streak_points + new_cells_points;
+    // points of every entrant in the game
+    let (new_game, preview) = (game_options.new_game(), game_options.preview);
+    let entrants = &options.entrants;
+    let parallelism = game_options.parallelism();
+    let play = |game: usize| -> Vec<i32> {
+        let seed = options.first_seed + game as u64;
+        let points = entrants
+            .iter()
+            .map(|entrant| {
+                let mut dealer = SeededDealer::new(seed);
+                let (game_state, _) = play_game(new_game.clone(), preview, &mut dealer, |game_state| {
+                    (entrant.strategy)(game_state, parallelism)
+                        .map(|best| best.action)
+                        .map_err(|e| e.to_string())
+                });
+                game_state.points
+            })
+            .collect::<Vec<_>>();
+        let results = entrants
+            .iter()
+            .zip(&points)
+            .map(|(entrant, points)| format!("{} {}", entrant.name, points))
+            .collect::<Vec<_>>();
+        println!("game {} (seed {}): {}", game + 1, seed, results.join(", "));
+        points
+    };
+    // parallel games are printed in the order they finish
+    let games = if game_options.parallel_games {
+        (0..options.games).into_par_iter().map(play).collect::<Vec<_>>()
+    } else {
+        (0..options.games).map(play).collect()
+    };
+
+    // scores[entrant][game]
+    let scores = (0..options.entrants.len())
+        .map(|i| games.iter().map(|points| points[i]).collect::<Vec<_>>())
+        .collect::<Vec<_>>();
+
+    let ratings = ratings(&scores);
+    let name_width = options.entrants.iter().map(|e| e.name.len()).max().unwrap().max(8);
+    println!();
+    println!(
+        "{:<width$} {:>8} {:>8} {:>8} {:>8} {:>8} {:>8}",
+        "strategy", "mean", "median", "std dev", "min", "max", "rating",
+        width = name_width
+    );
+    for (i, entrant) in options.entrants.iter().enumerate() {
+        let s = summary(&scores[i]);
+        println!(
+            "{:<width$} {:>8.0} {:>8.0} {:>8.0} {:>8} {:>8} {:>8.0}",
+            entrant.name, s.mean, s.median, s.std_dev, s.min, s.max, ratings[i],
+            width = name_width
+        );
+    }
 
-    GameState {
-        board,
-        points: game_state.points + bonus_points,
-        last_move_was_match: resolve_result.blocks_removed > 0,
-        available_bricks,
+    println!("\nhead to head (win rate, sign test p-value):");
+    for i in 0..options.entrants.len() {
+        for j in i + 1..options.entrants.len() {
+            let result = head_to_head(&scores[i], &scores[j]);
+            println!(
+                "  {} vs {}: {}-{}-{} (win rate {:.2}, p = {:.4})",
+                options.entrants[i].name,
+                options.entrants[j].name,
+                result.wins,
+                result.losses,
+                result.draws,
+                result.win_rate(),
+                result.p_value
+            );
+        }
     }
 }
 
-#[derive(Clone, Debug)]
-struct PossibleMove {
-    brick: Brick,
-    brick_index: i32,
-    pos: XY,
+struct TrainOptions {
+    data: Vec<String>,
+    output: String,
+    hidden: usize,
+    epochs: usize,
+    learning_rate: f32,
 }
 
-impl From<PossibleMove> for Move {
-    fn from(m: PossibleMove) -> Self {
-        Move{pos: m.pos, brick_index: m.brick_index}
+// Trains a network predicting the points scored until the end of the game on `selfplay` data.
+// The board size has to match the data - it's set with the game options.
+fn train(options: &TrainOptions, game_options: &GameOptions) {
+    if options.data.is_empty() {
+        println!("train needs self-play data - see `--data`");
+        return;
     }
-}
 
-fn get_possible_moves(game_state: &GameState) -> Vec<PossibleMove> {
-    let mut moves = vec![];
-    let mut brick_index = 0;
-    for brick in &game_state.available_bricks {
-        moves.extend(
-            possible_moves(&game_state.board, &brick)
-                .iter()
-                .map(|pos| PossibleMove {
-                    brick: brick.clone(),
-                    brick_index,
-                    pos: pos.clone(),
-                })
-                .collect::<Vec<_>>(),
-        );
-        brick_index = brick_index + 1;
+    let geometry = game_options.start_board.geometry();
+    let mut inputs = vec![];
+    let mut targets = vec![];
+    for path in &options.data {
+        let positions = match load_positions(path, geometry) {
+            Ok(positions) => positions,
+            Err(e) => {
+                println!("{}", e);
+                return;
+            }
+        };
+        for position in positions {
+            inputs.push(board_features(&position.board));
+            targets.push((position.final_points - position.points) as f32 / POINTS_SCALE);
+        }
+    }
+    println!("training on {} positions", inputs.len());
+
+    let input_size = board_features(&game_options.start_board).len();
+    let mut mlp = Mlp::new(&[input_size, options.hidden], 0);
+    let mut rng = StdRng::seed_from_u64(0);
+    for epoch in 1..=options.epochs {
+        match mlp.train_epoch(&inputs, &targets, options.learning_rate, &mut rng) {
+            Ok(loss) => println!("epoch {}: loss {}", epoch, loss),
+            Err(e) => {
+                println!("{}", e);
+                return;
+            }
+        }
+    }
+
+    if let Err(e) = std::fs::write(&options.output, mlp.save()) {
+        println!("can't write {}: {}", options.output, e);
+        return;
     }
+    println!("the model is saved to {} - use it with `--model={}`", options.output, options.output);
+}
 
-    moves
+// sessions start with the game options given on the command line
+fn serve_games(port: u16, game_options: &GameOptions) {
+    let listener = match std::net::TcpListener::bind(("127.0.0.1", port)) {
+        Ok(listener) => listener,
+        Err(e) => {
+            println!("can't listen on port {}: {}", port, e);
+            return;
+        }
+    };
+    println!("serving games on 127.0.0.1:{} (see `src/server.rs` for the protocol)", port);
+    serve(
+        listener,
+        ServerConfig {
+            rules: game_options.rules,
+            preview: game_options.preview,
+            start_board: game_options.start_board,
+            dealer: game_options.dealer,
+            evaluator: game_options.evaluator.clone(),
+        },
+    );
 }
 
-#[derive(Clone, Debug)]
-struct PossibleMoveScore {
-    possible_move: PossibleMove,
-    score: i32,
+fn print_usage() {
+    println!("usage:");
+    println!("  roku_doku [bot] [--games=N] [game options]   - let the bot play N games (default: 1)");
+    println!("  roku_doku play [--coach[=N]] [game options]  - play a game yourself (type `hint` to ask the bot for a move)");
+    println!("                                                 `--coach` warns about moves scoring N (default: 50) points below the bot's best move");
+    println!("  roku_doku referee --bot=COMMAND [--time-limit=MS] [--games=N] [game options]");
+    println!("                                               - let an external bot play against the built-in one (see `src/referee.rs`");
+    println!("                                                 for the protocol); the default time limit is 1000 ms per move, 1 game");
+    println!("  roku_doku selfplay [--games=N] [--output=FILE] [game options]");
+    println!("                                               - write the bot's games as training data (see `src/dataset.rs`),");
+    println!("                                                 1 game to `selfplay.csv` by default");
+    println!("  roku_doku tournament [--strategy=S]... [--games=N] [--seed=N] [game options]");
+    println!("                                               - compare strategies on the same bricks: `heuristic`, `greedy`, `random`");
+    println!("                                                 or `model:FILE` (all but the last by default), 10 games from seed 0 by default");
+    println!("  roku_doku train --data=FILE [--output=FILE] [--hidden=N] [--epochs=N] [--learning-rate=X] [game options]");
+    println!("                                               - train a network evaluating positions on `selfplay` data (`--data` can be");
+    println!("                                                 repeated), the defaults are `model.mlp`, 64 hidden units, 10 epochs, 0.001");
+    println!("  roku_doku serve [--port=N] [game options]    - host games for local clients over TCP with JSON messages");
+    println!("                                                 (see `src/server.rs` for the protocol), the default port is 7878");
+    println!();
+    println!("game options:");
+    println!("  --dealer=D     `random` (default) or `adversarial` - dealing hands which are hard to play");
+    println!("                 (at most {} bricks, no preview)", MAX_ADVERSARIAL_COUNT);
+    println!("  --preview=N    show N upcoming bricks (default: 0, at most 64)");
+    println!("  --hand-size=N  number of bricks in the hand (default: 3)");
+    println!("  --refill=R     refill the hand when it's `empty` (default) or top it up after `every-move`");
+    println!("  --level=FILE   start from a pre-filled board, possibly with stones (see `levels/`)");
+    println!("  --size=N       play on a NxN board (default: 9)");
+    println!("  --box-size=N   size of the boxes cleared like rows and columns, 0 for no boxes");
+    println!("                 (default: square root of the board size if it's a whole number, otherwise 0)");
+    println!("  --regions=FILE add diagonals and/or irregular boxes to the cleared regions (see `regions/`)");
+    println!("  --model=FILE   let the bot evaluate positions with a network trained with `train`");
+    println!("  --metrics=M    report counters of the engine's work as `text` (default), `json` or `off`");
+    println!("                 (in builds with `--features metrics`)");
+    println!("  --log=SPEC     log levels (`off`, `error`, `warn`, `info` - default, `debug`, `trace`) for all the targets");
+    println!("                 and/or single ones (`engine`, `search`, `ui`), e.g. `--log=warn,search=debug`");
+    println!("  --threads=N    number of threads the bot uses (default: one per core)");
+    println!("  --parallel=P   the bot searches the `moves` in parallel (default) or, in `bot`, `selfplay` and `tournament`,");
+    println!("                 plays the `games` in parallel, searching on a single thread");
+    println!("  --hold         allow parking a brick in the hold slot (`hold brick_no`) and swapping it back later");
+    println!("                 (the bot considers holding only for its next action, not deeper in its search)");
 }
 
-impl From<PossibleMoveScore> for Move {
-    fn from(m: PossibleMoveScore) -> Self {
-        m.possible_move.into()
+fn parse_dealer(name: &str) -> Option<DealerFactory> {
+    match name {
+        "random" => Some(|_, _| Box::new(RandomDealer)),
+        "adversarial" => Some(|evaluator, parallelism| {
+            Box::new(AdversarialDealer::new(4, evaluator.clone(), parallelism))
+        }),
+        _ => None,
     }
 }
 
-fn count_filled_cells(board: &Board) -> i32 {
-    board.iter()
-        .map(|row| row.iter()
-            .map(|cell| if *cell { 1 } else { 0 }).sum::<i32>())
-        .sum()
+// prints an error if the value can't be parsed
+fn parse_value<T: std::str::FromStr>(option: &str, value: &str) -> Option<T> {
+    let parsed = value.parse::<T>().ok();
+    if parsed.is_none() {
+        println!("invalid value of {}: {}", option, value);
+    }
+    parsed
 }
 
-// 6 in line -> +1 point
-// 7 in line -> +2 points
-// 8 in line -> +3 points
-fn find_almost_full_lines(board: &Board) -> i32 {
-    let mut bonus_points = 0;
-    for x in 0..9{
-        let mut count = 0;
-        for y in 0..9{
-            if board[y][x] {
-                count = count + 1;
-                if count > 5 {
-                    bonus_points = bonus_points + 1
-                }
+// returns None if the option is invalid (with an error printed)
+// or Some(false) if it isn't a game option
+fn parse_game_option(arg: &str, game_options: &mut GameOptions) -> Option<bool> {
+    if let Some(name) = arg.strip_prefix("--dealer=") {
+        game_options.dealer = parse_dealer(name).or_else(|| {
+            println!("unknown dealer: {}", name);
+            None
+        })?;
+    } else if let Some(preview) = arg.strip_prefix("--preview=") {
+        game_options.preview = parse_value("--preview", preview)?;
+        if game_options.preview > MAX_PREVIEW {
+            println!("preview should be at most {} bricks", MAX_PREVIEW);
+            return None;
+        }
+    } else if let Some(hand_size) = arg.strip_prefix("--hand-size=") {
+        game_options.rules.hand_size = parse_value("--hand-size", hand_size)?;
+        if game_options.rules.hand_size == 0 {
+            println!("hand size should be positive");
+            return None;
+        }
+    } else if let Some(path) = arg.strip_prefix("--level=") {
+        game_options.level_path = Some(path.to_string());
+    } else if let Some(path) = arg.strip_prefix("--regions=") {
+        game_options.regions_path = Some(path.to_string());
+    } else if let Some(size) = arg.strip_prefix("--size=") {
+        game_options.size = parse_value("--size", size)?;
+    } else if let Some(box_size) = arg.strip_prefix("--box-size=") {
+        game_options.box_size = Some(parse_value("--box-size", box_size)?);
+    } else if let Some(path) = arg.strip_prefix("--model=") {
+        match Mlp::load(path) {
+            Ok(mlp) => game_options.evaluator = Evaluator::Mlp(mlp),
+            Err(e) => {
+                println!("{}", e);
+                return None;
             }
         }
+    } else if let Some(output) = arg.strip_prefix("--metrics=") {
+        game_options.metrics = match output {
+            "text" => MetricsOutput::Text,
+            "json" => MetricsOutput::Json,
+            "off" => MetricsOutput::Off,
+            _ => {
+                println!("unknown metrics output: {}", output);
+                return None;
+            }
+        };
+        metrics::set_enabled(game_options.metrics != MetricsOutput::Off);
+    } else if let Some(spec) = arg.strip_prefix("--log=") {
+        if let Err(e) = log::configure(spec) {
+            println!("{}", e);
+            return None;
+        }
+    } else if let Some(threads) = arg.strip_prefix("--threads=") {
+        game_options.threads = Some(parse_value("--threads", threads)?);
+        if game_options.threads == Some(0) {
+            println!("number of threads should be positive");
+            return None;
+        }
+    } else if let Some(parallel) = arg.strip_prefix("--parallel=") {
+        game_options.parallel_games = match parallel {
+            "moves" => false,
+            "games" => true,
+            _ => {
+                println!("unknown parallelism: {}", parallel);
+                return None;
+            }
+        };
+    } else if arg == "--hold" {
+        game_options.rules.hold = true;
+    } else if let Some(name) = arg.strip_prefix("--refill=") {
+        game_options.rules.refill_policy = RefillPolicy::from_name(name).or_else(|| {
+            println!("unknown refill policy: {}", name);
+            None
+        })?;
+    } else {
+        return Some(false);
     }
 
-    for y in 0..9{
-        let mut count = 0;
-        for x in 0..9{
-            if board[y][x] {
-                count = count + 1;
-                if count > 5 {
-                    bonus_points = bonus_points + 1
+    Some(true)
+}
+
+fn main() {
+    let args = std::env::args().skip(1).collect::<Vec<_>>();
+    let (command, options) = match args.first() {
+        Some(arg) if !arg.starts_with("--") => (arg.as_str(), &args[1..]),
+        _ => ("bot", &args[..]),
+    };
+
+    let mut game_options = GameOptions {
+        rules: Rules::default(),
+        preview: 0,
+        dealer: |_, _| Box::new(RandomDealer),
+        size: 9,
+        box_size: None,
+        level_path: None,
+        regions_path: None,
+        start_board: Board::default(),
+        evaluator: Evaluator::Heuristic,
+        metrics: MetricsOutput::Text,
+        threads: None,
+        parallel_games: false,
+    };
+    let mut play_options = PlayOptions {
+        coach_threshold: None,
+    };
+    let mut referee_options = RefereeOptions {
+        bot_command: None,
+        time_limit: Duration::from_millis(1000),
+        games: 1,
+    };
+    let mut self_play_options = SelfPlayOptions {
+        games: 1,
+        output: "selfplay.csv".to_string(),
+    };
+    let mut train_options = TrainOptions {
+        data: vec![],
+        output: "model.mlp".to_string(),
+        hidden: 64,
+        epochs: 10,
+        learning_rate: 0.001,
+    };
+    let mut tournament_options = TournamentOptions {
+        entrants: vec![],
+        games: 10,
+        first_seed: 0,
+    };
+    let mut bot_game_count = 1;
+    let mut port = 7878;
+    for arg in options {
+        match parse_game_option(arg, &mut game_options) {
+            Some(true) => continue,
+            Some(false) => {}
+            None => return,
+        }
+
+        if command == "play" && (arg == "--coach" || arg.starts_with("--coach=")) {
+            let threshold = arg.strip_prefix("--coach=").unwrap_or("50");
+            match parse_value("--coach", threshold) {
+                Some(threshold) => play_options.coach_threshold = Some(threshold),
+                None => return,
+            }
+        } else if let (true, Some(bot_command)) = (command == "referee", arg.strip_prefix("--bot=")) {
+            referee_options.bot_command = Some(bot_command.to_string());
+        } else if let (true, Some(time_limit)) = (command == "referee", arg.strip_prefix("--time-limit=")) {
+            match parse_value("--time-limit", time_limit) {
+                Some(time_limit) => referee_options.time_limit = Duration::from_millis(time_limit),
+                None => return,
+            }
+        } else if let (true, Some(games)) = (command == "referee", arg.strip_prefix("--games=")) {
+            match parse_value("--games", games) {
+                Some(games) if games > 0 => referee_options.games = games,
+                _ => return,
+            }
+        } else if let (true, Some(games)) = (command == "selfplay", arg.strip_prefix("--games=")) {
+            match parse_value("--games", games) {
+                Some(games) if games > 0 => self_play_options.games = games,
+                _ => return,
+            }
+        } else if let (true, Some(output)) = (command == "selfplay", arg.strip_prefix("--output=")) {
+            self_play_options.output = output.to_string();
+        } else if let (true, Some(games)) = (command == "bot", arg.strip_prefix("--games=")) {
+            match parse_value("--games", games) {
+                Some(games) if games > 0 => bot_game_count = games,
+                _ => return,
+            }
+        } else if let (true, Some(name)) = (command == "tournament", arg.strip_prefix("--strategy=")) {
+            match parse_strategy(name) {
+                Ok(entrant) => tournament_options.entrants.push(entrant),
+                Err(e) => {
+                    println!("{}", e);
+                    return;
                 }
             }
+        } else if let (true, Some(games)) = (command == "tournament", arg.strip_prefix("--games=")) {
+            match parse_value("--games", games) {
+                Some(games) if games > 0 => tournament_options.games = games,
+                _ => return,
+            }
+        } else if let (true, Some(seed)) = (command == "tournament", arg.strip_prefix("--seed=")) {
+            match parse_value("--seed", seed) {
+                Some(seed) => tournament_options.first_seed = seed,
+                None => return,
+            }
+        } else if let (true, Some(path)) = (command == "train", arg.strip_prefix("--data=")) {
+            train_options.data.push(path.to_string());
+        } else if let (true, Some(path)) = (command == "train", arg.strip_prefix("--output=")) {
+            train_options.output = path.to_string();
+        } else if let (true, Some(hidden)) = (command == "train", arg.strip_prefix("--hidden=")) {
+            match parse_value("--hidden", hidden) {
+                Some(hidden) if hidden > 0 => train_options.hidden = hidden,
+                _ => return,
+            }
+        } else if let (true, Some(epochs)) = (command == "train", arg.strip_prefix("--epochs=")) {
+            match parse_value("--epochs", epochs) {
+                Some(epochs) => train_options.epochs = epochs,
+                None => return,
+            }
+        } else if let (true, Some(learning_rate)) = (command == "train", arg.strip_prefix("--learning-rate=")) {
+            match parse_value("--learning-rate", learning_rate) {
+                Some(learning_rate) => train_options.learning_rate = learning_rate,
+                None => return,
+            }
+        } else if let (true, Some(value)) = (command == "serve", arg.strip_prefix("--port=")) {
+            match parse_value("--port", value) {
+                Some(value) => port = value,
+                None => return,
+            }
+        } else {
+            println!("unknown option: {}", arg);
+            print_usage();
+            return;
         }
     }
 
-    bonus_points
-}
-
-fn ai_submove(game_state: &GameState, m: &PossibleMove) -> PossibleMoveScore {
-    let s = perform_move(&game_state, &m.clone().into());
-    if s.available_bricks.is_empty(){
-        return PossibleMoveScore{
-            possible_move: m.clone(),
-            score: s.points
-                - count_filled_cells(&s.board) * 1
-                + find_almost_full_lines(&s.board) * 2
-        };
+    if let Err(e) = game_options.prepare() {
+        println!("{}", e);
+        return;
     }
 
-    let moves = get_possible_moves(&s);
-    if moves.is_empty() {
-        return PossibleMoveScore{possible_move: m.clone(), score: s.points - 1000};
+    match command {
+        "bot" => bot_games(bot_game_count, &game_options),
+        "play" => play(&play_options, &game_options),
+        "referee" => referee(&referee_options, &game_options),
+        "serve" => serve_games(port, &game_options),
+        "selfplay" => self_play(&self_play_options, &game_options),
+        "train" => train(&train_options, &game_options),
+        "tournament" => tournament(&mut tournament_options, &game_options),
+        _ => print_usage(),
     }
+}
 
-    let best_sub_move = ai_move(&s);
-    PossibleMoveScore{possible_move: m.clone(), score: best_sub_move.score}
+#[derive(Clone, Copy, PartialEq)]
+enum MetricsOutput {
+    Text,
+    Json,
+    Off,
 }
 
-// tests avg score on 100 games:
-// 1) random move: 74
-// 2) min brick_index, then min x, then min y: 182
-// 3 )do move which gives the most points: 458
-// 4 )do 3 moves which gives the most points: 1858
-// 5) 4) + subtract 2 points for each filled cell: 1744
-// 6) 4) + subtract 1 points for each filled cell: 1807
-// 7) 4) : 1680
-// 8) 7) + bonus points for "almost full lines": 6089
-// Added missing bricks (brick_11 and brick)12) in this point
-// 8) 7) + bonus points for "almost full lines" * 2:
-fn ai_move(game_state: &GameState) -> PossibleMoveScore {
-    let moves = get_possible_moves(game_state);
-    moves
-        .par_iter()
-        // .iter() // to use single thread
-        .map(|m| ai_submove(&game_state, &m))
-        .max_by_key(|x| x.score)
-        .unwrap()
+// nothing is counted without the `metrics` feature
+fn print_metrics(output: MetricsOutput, label: &str, metrics: &Metrics, elapsed: Duration) {
+    if !metrics::is_enabled() {
+        return;
+    }
+    match output {
+        MetricsOutput::Text => println!("{} metrics:\n{}", label, metrics.report(elapsed)),
+        MetricsOutput::Json => println!("{}", json!({ "metrics": label, "values": metrics.to_json(elapsed) })),
+        MetricsOutput::Off => {}
+    }
 }
 
-fn main() {
-    // print_all_bricks();
+// a game of the bot, returns the points
+fn bot_game(game_options: &GameOptions) -> i32 {
+    let mut move_counter = 0;
+    let mut game_state = game_options.new_game();
+    let mut dealer = game_options.new_dealer();
+    let mut queue = BrickQueue::new(game_options.preview);
+    loop {
+        queue.refill(&mut game_state, dealer.as_mut());
 
-    let mut scores = vec![];
-    let start = Instant::now();
-    for _ in 0..1{ // 00 {
-        let mut move_counter = 0;
-        let mut game_state = GameState::new();
-        loop {
-            if game_state.available_bricks.is_empty() {
-                game_state.available_bricks = vec![random_brick(), random_brick(), random_brick()];
-            }
+        if log::enabled(Target::Ui, Level::Trace) {
+            print_game_state(&game_state);
+        }
 
-            // print_game_state(&game_state);
-
-            let possible_move_count = game_state
-                .available_bricks
-                .iter()
-                .map(|brick| possible_moves(&&game_state.board, brick).len())
-                .sum::<usize>();
-            if possible_move_count == 0 {
-                println!("game over!\n your score: {} (in {} moves)", game_state.points, &move_counter);
-                scores.push(game_state.points);
-                break;
-            } else {
-                // println!("\nnumber of possible moves: {}", &possible_move_count);
+        if is_game_over(&game_state) {
+            println!("game over!\n your score: {} (in {} moves)", game_state.points, &move_counter);
+            print_cleared_regions(&game_state);
+            return game_state.points;
+        }
+        debug!(Target::Engine, "number of possible moves: {}", get_possible_moves(&game_state).len());
+
+        let move_start = Instant::now();
+        let best = ai_action_with(&game_state, &game_options.evaluator, game_options.parallelism());
+        metrics::record_move_time(move_start.elapsed());
+        let next_state = best.and_then(|best| {
+            debug!(Target::Engine, "executing move: {}", format_action(&best.action));
+            perform_action(&game_state, &best.action)
+        });
+        match next_state {
+            Ok(next_state) => game_state = next_state,
+            Err(e) => {
+                println!("the bot can't move: {}", e);
+                return game_state.points;
             }
+        }
+        move_counter += 1;
 
-            // let user_move = read_user_move(&game_state);
-            let user_move = ai_move(&game_state).into();
-            // println!("executing move: {:?}", &user_move);
+        if move_counter % 100 == 0 {
+            info!(Target::Engine, "{} moves done", move_counter);
+        }
+    }
+}
 
-            game_state = perform_move(&game_state, &user_move);
-            move_counter = move_counter + 1;
+fn bot_games(games: usize, game_options: &GameOptions) {
+    // print_all_bricks();
 
-            if move_counter % 100 == 0 && move_counter != 0 {
-                println!("{} moves done", move_counter);
+    let start = Instant::now();
+    let (scores, total_metrics) = if game_options.parallel_games {
+        // the counters of parallel games can't be told apart, only the total is reported
+        metrics::reset();
+        let scores = (1..=games).into_par_iter().map(|_| bot_game(game_options)).collect::<Vec<_>>();
+        (scores, metrics::snapshot())
+    } else {
+        let mut scores = vec![];
+        let mut total_metrics = Metrics::default();
+        for game in 1..=games {
+            let game_start = Instant::now();
+            metrics::reset();
+            scores.push(bot_game(game_options));
+
+            let game_metrics = metrics::snapshot();
+            total_metrics = total_metrics.merge(&game_metrics);
+            if games > 1 {
+                print_metrics(game_options.metrics, &format!("game {}", game), &game_metrics, game_start.elapsed());
             }
         }
-    }
+        (scores, total_metrics)
+    };
     let duration = start.elapsed();
 
     println!("final scores: {:#?}", scores);
     println!("min score: {:#?}", scores.iter().min().unwrap());
     println!("max score: {:#?}", scores.iter().max().unwrap());
     println!("avg score: {:#?}", scores.iter().sum::<i32>() / scores.len() as i32);
-    println!("Time elapsed: {:?}s", duration);
+    println!("Time elapsed: {:?}", duration);
 
-    println!("PUT_BRICK_COUNTER: {} ({} per second)", PUT_BRICK_COUNTER.get(), PUT_BRICK_COUNTER.get() as u64 / duration.as_secs());
-    println!("CAN_PUT_BRICK_COUNTER: {} ({} per second)", CAN_PUT_BRICK_COUNTER.get(), CAN_PUT_BRICK_COUNTER.get() as u64 / duration.as_secs());
-    println!("POSSIBLE_MOVES_COUNTER: {} ({} per second)", POSSIBLE_MOVES_COUNTER.get(), POSSIBLE_MOVES_COUNTER.get() as u64 / duration.as_secs());
+    print_metrics(game_options.metrics, "total", &total_metrics, duration);
 }
 
-// TODO: write some macro to disable printing (as it takes a lot of time)
-// TODO: do profiling
-// TODO: multithreading
 // TODO: penalty for "holes"
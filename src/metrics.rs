@@ -0,0 +1,305 @@
+#[cfg(feature = "metrics")]
+use lazy_static::lazy_static;
+use serde_json::{json, Map, Value};
+#[cfg(feature = "metrics")]
+use std::sync::atomic::AtomicU64;
+use std::sync::atomic::{AtomicBool, Ordering};
+#[cfg(feature = "metrics")]
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+// Counters can be bumped from anywhere (including the search threads) without passing anything
+// around. Every thread has its own counters, so the search threads don't contend on them, and
+// `snapshot` adds them up. They're compiled in only with the `metrics` feature (off by default),
+// and can be switched off at runtime with `set_enabled`.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum Counter {
+    PutBrick,
+    CanPutBrick,
+    PossibleMoves,
+    // positions searched by the bot (after each checked move)
+    SearchNodes,
+    // calls of the search and the number of moves checked by them - for the branching factor
+    Searches,
+    SearchBranches,
+    // positions scored by the evaluator and the sum of their depths
+    SearchLeaves,
+    SearchLeafDepth,
+    // positions found in the feasibility check's table of dead positions
+    TranspositionHits,
+    Moves,
+    MoveTimeMicros,
+    MaxMoveTimeMicros,
+}
+
+pub const COUNTERS: [Counter; 12] = [
+    Counter::PutBrick,
+    Counter::CanPutBrick,
+    Counter::PossibleMoves,
+    Counter::SearchNodes,
+    Counter::Searches,
+    Counter::SearchBranches,
+    Counter::SearchLeaves,
+    Counter::SearchLeafDepth,
+    Counter::TranspositionHits,
+    Counter::Moves,
+    Counter::MoveTimeMicros,
+    Counter::MaxMoveTimeMicros,
+];
+
+impl Counter {
+    pub fn name(self) -> &'static str {
+        match self {
+            Counter::PutBrick => "put_brick",
+            Counter::CanPutBrick => "can_put_brick",
+            Counter::PossibleMoves => "possible_moves",
+            Counter::SearchNodes => "search_nodes",
+            Counter::Searches => "searches",
+            Counter::SearchBranches => "search_branches",
+            Counter::SearchLeaves => "search_leaves",
+            Counter::SearchLeafDepth => "search_leaf_depth",
+            Counter::TranspositionHits => "transposition_hits",
+            Counter::Moves => "moves",
+            Counter::MoveTimeMicros => "move_time_us",
+            Counter::MaxMoveTimeMicros => "max_move_time_us",
+        }
+    }
+
+    // merged by taking the bigger value instead of adding them up
+    fn is_max(self) -> bool {
+        self == Counter::MaxMoveTimeMicros
+    }
+}
+
+#[cfg(feature = "metrics")]
+type Values = [AtomicU64; COUNTERS.len()];
+
+#[cfg(feature = "metrics")]
+lazy_static! {
+    // the counters of every thread which has counted something, also of the finished ones
+    static ref THREADS: Mutex<Vec<Arc<Values>>> = Mutex::new(vec![]);
+}
+
+#[cfg(feature = "metrics")]
+thread_local! {
+    static VALUES: Arc<Values> = {
+        let values = Arc::new([const { AtomicU64::new(0) }; COUNTERS.len()]);
+        THREADS.lock().unwrap().push(values.clone());
+        values
+    };
+}
+
+// Only the owning thread changes its counters, so they're updated without atomic
+// read-modify-writes. A `reset` while other threads count may miss some of their updates.
+#[cfg(feature = "metrics")]
+fn update(counter: Counter, f: impl FnOnce(u64) -> u64) {
+    VALUES.with(|values| {
+        let value = &values[counter as usize];
+        value.store(f(value.load(Ordering::Relaxed)), Ordering::Relaxed);
+    });
+}
+
+static ENABLED: AtomicBool = AtomicBool::new(true);
+
+pub fn set_enabled(enabled: bool) {
+    ENABLED.store(enabled, Ordering::Relaxed);
+}
+
+pub fn is_enabled() -> bool {
+    cfg!(feature = "metrics") && ENABLED.load(Ordering::Relaxed)
+}
+
+#[inline]
+pub fn inc(counter: Counter) {
+    add(counter, 1);
+}
+
+#[inline]
+#[allow(unused_variables)]
+pub fn add(counter: Counter, value: u64) {
+    #[cfg(feature = "metrics")]
+    if ENABLED.load(Ordering::Relaxed) {
+        update(counter, |old| old + value);
+    }
+}
+
+#[inline]
+#[allow(unused_variables)]
+pub fn record_max(counter: Counter, value: u64) {
+    #[cfg(feature = "metrics")]
+    if ENABLED.load(Ordering::Relaxed) {
+        update(counter, |old| old.max(value));
+    }
+}
+
+pub fn record_move_time(duration: Duration) {
+    let micros = duration.as_micros() as u64;
+    inc(Counter::Moves);
+    add(Counter::MoveTimeMicros, micros);
+    record_max(Counter::MaxMoveTimeMicros, micros);
+}
+
+pub fn reset() {
+    #[cfg(feature = "metrics")]
+    for values in THREADS.lock().unwrap().iter() {
+        for value in values.iter() {
+            value.store(0, Ordering::Relaxed);
+        }
+    }
+}
+
+// the counters of all the threads together
+pub fn snapshot() -> Metrics {
+    #[allow(unused_mut)]
+    let mut metrics = Metrics::default();
+    #[cfg(feature = "metrics")]
+    for values in THREADS.lock().unwrap().iter() {
+        let mut thread_metrics = Metrics::default();
+        for (i, value) in values.iter().enumerate() {
+            thread_metrics.values[i] = value.load(Ordering::Relaxed);
+        }
+        metrics = metrics.merge(&thread_metrics);
+    }
+    metrics
+}
+
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct Metrics {
+    values: [u64; COUNTERS.len()],
+}
+
+impl Metrics {
+    pub fn get(&self, counter: Counter) -> u64 {
+        self.values[counter as usize]
+    }
+
+    // e.g. for totals of many games
+    pub fn merge(&self, other: &Metrics) -> Metrics {
+        let mut merged = *self;
+        for counter in COUNTERS.iter() {
+            let i = *counter as usize;
+            merged.values[i] = if counter.is_max() {
+                self.values[i].max(other.values[i])
+            } else {
+                self.values[i] + other.values[i]
+            };
+        }
+        merged
+    }
+
+    fn ratio(&self, counter: Counter, divisor: Counter) -> f64 {
+        self.get(counter) as f64 / self.get(divisor).max(1) as f64
+    }
+
+    pub fn branching_factor(&self) -> f64 {
+        self.ratio(Counter::SearchBranches, Counter::Searches)
+    }
+
+    pub fn average_leaf_depth(&self) -> f64 {
+        self.ratio(Counter::SearchLeafDepth, Counter::SearchLeaves)
+    }
+
+    pub fn average_move_time(&self) -> Duration {
+        Duration::from_micros(self.ratio(Counter::MoveTimeMicros, Counter::Moves) as u64)
+    }
+
+    pub fn report(&self, elapsed: Duration) -> String {
+        let per_second = |counter| (self.get(counter) as f64 / elapsed.as_secs_f64().max(1e-9)) as u64;
+        let mut lines = vec![];
+        for &counter in &[Counter::PutBrick, Counter::CanPutBrick, Counter::PossibleMoves, Counter::SearchNodes] {
+            lines.push(format!("{}: {} ({} per second)", counter.name(), self.get(counter), per_second(counter)));
+        }
+        lines.push(format!(
+            "search: branching factor {:.1}, average leaf depth {:.2}, transposition hits {}",
+            self.branching_factor(),
+            self.average_leaf_depth(),
+            self.get(Counter::TranspositionHits)
+        ));
+        lines.push(format!(
+            "time per move: {:?} on average, {:?} at most ({} moves)",
+            self.average_move_time(),
+            Duration::from_micros(self.get(Counter::MaxMoveTimeMicros)),
+            self.get(Counter::Moves)
+        ));
+        lines.join("\n")
+    }
+
+    pub fn to_json(&self, elapsed: Duration) -> Value {
+        let mut counters = Map::new();
+        for &counter in COUNTERS.iter() {
+            counters.insert(counter.name().to_string(), json!(self.get(counter)));
+        }
+        json!({
+            "elapsed_s": elapsed.as_secs_f64(),
+            "counters": counters,
+            "branching_factor": self.branching_factor(),
+            "average_leaf_depth": self.average_leaf_depth(),
+            "average_move_time_us": self.average_move_time().as_micros() as u64,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn metrics(values: &[(Counter, u64)]) -> Metrics {
+        let mut metrics = Metrics::default();
+        for (counter, value) in values {
+            metrics.values[*counter as usize] = *value;
+        }
+        metrics
+    }
+
+    #[test]
+    fn test_merge() {
+        let a = metrics(&[(Counter::Moves, 2), (Counter::MaxMoveTimeMicros, 50)]);
+        let b = metrics(&[(Counter::Moves, 3), (Counter::MaxMoveTimeMicros, 20)]);
+        let merged = a.merge(&b);
+        assert_eq!(merged.get(Counter::Moves), 5);
+        assert_eq!(merged.get(Counter::MaxMoveTimeMicros), 50);
+    }
+
+    #[test]
+    fn test_derived_values() {
+        let m = metrics(&[
+            (Counter::Searches, 2),
+            (Counter::SearchBranches, 30),
+            (Counter::SearchLeaves, 4),
+            (Counter::SearchLeafDepth, 10),
+            (Counter::Moves, 2),
+            (Counter::MoveTimeMicros, 3000),
+        ]);
+        assert_eq!(m.branching_factor(), 15.0);
+        assert_eq!(m.average_leaf_depth(), 2.5);
+        assert_eq!(m.average_move_time(), Duration::from_micros(1500));
+        assert_eq!(Metrics::default().branching_factor(), 0.0);
+    }
+
+    #[cfg(feature = "metrics")]
+    #[test]
+    fn test_counters_of_all_threads() {
+        // nothing else counts moves in the tests
+        let before = snapshot().get(Counter::Moves);
+        std::thread::scope(|scope| {
+            for i in 0..4 {
+                scope.spawn(move || {
+                    for _ in 0..100 {
+                        inc(Counter::Moves);
+                    }
+                    record_max(Counter::MaxMoveTimeMicros, 1_000_000_000_000 + i);
+                });
+            }
+        });
+        assert_eq!(snapshot().get(Counter::Moves), before + 400);
+        assert_eq!(snapshot().get(Counter::MaxMoveTimeMicros), 1_000_000_000_003);
+    }
+
+    #[test]
+    fn test_json() {
+        let m = metrics(&[(Counter::PutBrick, 7)]);
+        let json = m.to_json(Duration::from_secs(2));
+        assert_eq!(json["counters"]["put_brick"], 7);
+        assert_eq!(json["elapsed_s"], 2.0);
+    }
+}
@@ -0,0 +1,325 @@
+use crate::error::Error;
+use crate::{Board, Cell};
+use rand::rngs::StdRng;
+use rand::seq::SliceRandom;
+use rand::{Rng, SeedableRng};
+use rayon::prelude::*;
+
+// The network predicts points scored until the end of the game, scaled down to keep the
+// values small during training.
+pub const POINTS_SCALE: f32 = 1000.0;
+
+// Features of a board:
+// - filled cells (1.0 for any filled cell, row by row),
+// - permanent cells (1.0 for stones and double stones).
+pub fn board_features(board: &Board) -> Vec<f32> {
+    let cells = board.rows().flatten().collect::<Vec<_>>();
+    let mut features = Vec::with_capacity(2 * cells.len());
+    features.extend(cells.iter().map(|cell| cell.is_filled() as i32 as f32));
+    features.extend(
+        cells
+            .iter()
+            .map(|&&cell| (cell == Cell::Stone || cell == Cell::DoubleStone) as i32 as f32),
+    );
+    features
+}
+
+#[derive(Clone, Debug, PartialEq)]
+struct Layer {
+    inputs: usize,
+    outputs: usize,
+    // `outputs` rows of `inputs` weights
+    weights: Vec<f32>,
+    biases: Vec<f32>,
+}
+
+impl Layer {
+    fn forward(&self, input: &[f32], relu: bool) -> Vec<f32> {
+        (0..self.outputs)
+            .map(|o| {
+                let row = &self.weights[o * self.inputs..(o + 1) * self.inputs];
+                let sum = row.iter().zip(input).map(|(w, x)| w * x).sum::<f32>() + self.biases[o];
+                if relu {
+                    sum.max(0.0)
+                } else {
+                    sum
+                }
+            })
+            .collect()
+    }
+}
+
+// Multilayer perceptron with ReLU hidden layers and a single linear output.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Mlp {
+    layers: Vec<Layer>,
+}
+
+impl Mlp {
+    // `sizes` - number of inputs, then sizes of the hidden layers
+    pub fn new(sizes: &[usize], seed: u64) -> Mlp {
+        let mut rng = StdRng::seed_from_u64(seed);
+        let mut sizes = sizes.to_vec();
+        sizes.push(1);
+        let layers = sizes
+            .windows(2)
+            .map(|pair| {
+                let (inputs, outputs) = (pair[0], pair[1]);
+                // He initialization
+                let range = (6.0 / inputs as f32).sqrt();
+                Layer {
+                    inputs,
+                    outputs,
+                    weights: (0..inputs * outputs).map(|_| rng.gen_range(-range, range)).collect(),
+                    biases: vec![0.0; outputs],
+                }
+            })
+            .collect();
+        Mlp { layers }
+    }
+
+    pub fn input_size(&self) -> usize {
+        self.layers[0].inputs
+    }
+
+    // outputs of all the layers, starting with the input
+    fn activations(&self, input: &[f32]) -> Vec<Vec<f32>> {
+        let mut activations = vec![input.to_vec()];
+        for (i, layer) in self.layers.iter().enumerate() {
+            let relu = i + 1 < self.layers.len();
+            let output = layer.forward(activations.last().unwrap(), relu);
+            activations.push(output);
+        }
+        activations
+    }
+
+    // The input size is checked once where the network is set up (see `check_input_size`).
+    pub fn forward(&self, input: &[f32]) -> f32 {
+        debug_assert_eq!(input.len(), self.input_size());
+        self.activations(input).last().unwrap()[0]
+    }
+
+    pub fn forward_batch(&self, inputs: &[Vec<f32>]) -> Vec<f32> {
+        inputs.par_iter().map(|input| self.forward(input)).collect()
+    }
+
+    pub fn check_input_size(&self, input_size: usize) -> Result<(), Error> {
+        if input_size != self.input_size() {
+            return Err(Error::Parse(format!(
+                "the model expects {} inputs, found {}",
+                self.input_size(),
+                input_size
+            )));
+        }
+        Ok(())
+    }
+
+    // Stochastic gradient descent minimizing the squared error. Returns the mean squared error
+    // of the last epoch.
+    pub fn train(&mut self, inputs: &[Vec<f32>], targets: &[f32], epochs: usize, learning_rate: f32) -> Result<f32, Error> {
+        let mut rng = StdRng::seed_from_u64(0);
+        let mut loss = 0.0;
+        for _ in 0..epochs {
+            loss = self.train_epoch(inputs, targets, learning_rate, &mut rng)?;
+        }
+        Ok(loss)
+    }
+
+    // a single pass over the samples in random order
+    pub fn train_epoch<R: Rng>(
+        &mut self,
+        inputs: &[Vec<f32>],
+        targets: &[f32],
+        learning_rate: f32,
+        rng: &mut R,
+    ) -> Result<f32, Error> {
+        if inputs.len() != targets.len() {
+            return Err(Error::Parse(format!("{} inputs, but {} targets", inputs.len(), targets.len())));
+        }
+        for input in inputs {
+            self.check_input_size(input.len())?;
+        }
+
+        let mut order = (0..inputs.len()).collect::<Vec<_>>();
+        order.shuffle(rng);
+
+        let mut loss = 0.0;
+        for &i in &order {
+            loss += self.train_sample(&inputs[i], targets[i], learning_rate);
+        }
+        Ok(loss / inputs.len().max(1) as f32)
+    }
+
+    fn train_sample(&mut self, input: &[f32], target: f32, learning_rate: f32) -> f32 {
+        let activations = self.activations(input);
+        let error = activations.last().unwrap()[0] - target;
+
+        // gradient of the loss with respect to the outputs of the current layer
+        let mut gradient = vec![2.0 * error];
+        for (i, layer) in self.layers.iter_mut().enumerate().rev() {
+            let input = &activations[i];
+            let mut input_gradient = vec![0.0; layer.inputs];
+            for (o, &g) in gradient.iter().enumerate() {
+                if g == 0.0 {
+                    continue;
+                }
+                let row = &mut layer.weights[o * layer.inputs..(o + 1) * layer.inputs];
+                for (j, w) in row.iter_mut().enumerate() {
+                    input_gradient[j] += g * *w;
+                    *w -= learning_rate * g * input[j];
+                }
+                layer.biases[o] -= learning_rate * g;
+            }
+
+            // ReLU passes the gradient only for positive outputs of the previous layer
+            if i > 0 {
+                for (g, &x) in input_gradient.iter_mut().zip(input) {
+                    if x <= 0.0 {
+                        *g = 0.0;
+                    }
+                }
+            }
+            gradient = input_gradient;
+        }
+
+        error * error
+    }
+
+    // Plain text format:
+    //   mlp <number of inputs> <sizes of the following layers...>
+    //   for each layer: a line of weights for each output, then a line of biases
+    pub fn save(&self) -> String {
+        let mut sizes = vec![self.input_size()];
+        sizes.extend(self.layers.iter().map(|layer| layer.outputs));
+        let mut text = format!(
+            "mlp {}\n",
+            sizes.iter().map(|s| s.to_string()).collect::<Vec<_>>().join(" ")
+        );
+
+        let line = |values: &[f32]| values.iter().map(|v| v.to_string()).collect::<Vec<_>>().join(" ") + "\n";
+        for layer in &self.layers {
+            for row in layer.weights.chunks(layer.inputs) {
+                text += &line(row);
+            }
+            text += &line(&layer.biases);
+        }
+        text
+    }
+
+    pub fn parse(text: &str) -> Result<Mlp, Error> {
+        let mut lines = text.lines();
+        let sizes = lines
+            .next()
+            .and_then(|line| line.strip_prefix("mlp "))
+            .ok_or_else(|| Error::Parse("the model should start with `mlp` and the layer sizes".to_string()))?
+            .split_whitespace()
+            .map(|s| s.parse::<usize>().map_err(|_| Error::Parse(format!("invalid layer size: {}", s))))
+            .collect::<Result<Vec<_>, _>>()?;
+        if sizes.len() < 2 || *sizes.last().unwrap() != 1 {
+            return Err(Error::Parse("the model should have at least one layer and a single output".to_string()));
+        }
+        if sizes.contains(&0) {
+            return Err(Error::Parse("the inputs and the layers of the model shouldn't be empty".to_string()));
+        }
+
+        let mut read_values = |count: usize| -> Result<Vec<f32>, Error> {
+            let line = lines.next().ok_or_else(|| Error::Parse("the model is truncated".to_string()))?;
+            let values = line
+                .split_whitespace()
+                .map(|v| v.parse::<f32>().map_err(|_| Error::Parse(format!("invalid number: {}", v))))
+                .collect::<Result<Vec<_>, _>>()?;
+            if values.len() != count {
+                return Err(Error::Parse(format!("expected {} values, found {}", count, values.len())));
+            }
+            Ok(values)
+        };
+
+        let mut layers = vec![];
+        for pair in sizes.windows(2) {
+            let (inputs, outputs) = (pair[0], pair[1]);
+            let mut weights = vec![];
+            for _ in 0..outputs {
+                weights.extend(read_values(inputs)?);
+            }
+            let biases = read_values(outputs)?;
+            layers.push(Layer {
+                inputs,
+                outputs,
+                weights,
+                biases,
+            });
+        }
+        Ok(Mlp { layers })
+    }
+
+    pub fn load(path: &str) -> Result<Mlp, Error> {
+        let text = std::fs::read_to_string(path)
+            .map_err(|e| Error::Io(format!("can't read model file {}: {}", path, e)))?;
+        Mlp::parse(&text).map_err(|e| Error::Parse(format!("invalid model file {}: {}", path, e)))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::brick::xy;
+
+    #[test]
+    fn test_board_features() {
+        let mut board = Board::default();
+        board.set(xy(1, 0), Cell::Filled);
+        board.set(xy(8, 8), Cell::Stone);
+
+        let features = board_features(&board);
+        assert_eq!(features.len(), 162);
+        assert_eq!(features[1], 1.0);
+        assert_eq!(features[80], 1.0);
+        assert_eq!(features[81 + 80], 1.0);
+        assert_eq!(features.iter().sum::<f32>(), 3.0);
+    }
+
+    #[test]
+    fn test_save_and_parse() {
+        let mlp = Mlp::new(&[4, 3], 1);
+        let parsed = Mlp::parse(&mlp.save()).unwrap();
+        let input = [1.0, 0.0, 1.0, 1.0];
+        assert_eq!(parsed.forward(&input), mlp.forward(&input));
+
+        assert!(Mlp::parse("").is_err());
+        assert!(Mlp::parse("mlp 4 3 1\n1 2 3").is_err());
+        // `save` can't write empty layers
+        assert!(matches!(Mlp::parse("mlp 0 1\n\n0"), Err(Error::Parse(_))));
+        assert!(matches!(Mlp::parse("mlp 4 0 1\n\n\n0"), Err(Error::Parse(_))));
+    }
+
+    #[test]
+    fn test_forward_batch() {
+        let mlp = Mlp::new(&[2, 4], 1);
+        let inputs = vec![vec![0.0, 1.0], vec![1.0, 1.0]];
+        assert_eq!(mlp.forward_batch(&inputs), vec![mlp.forward(&inputs[0]), mlp.forward(&inputs[1])]);
+    }
+
+    #[test]
+    fn test_training_learns_a_function() {
+        // output = number of set inputs
+        let inputs = (0..8)
+            .map(|i| (0..3).map(|bit| ((i >> bit) & 1) as f32).collect::<Vec<_>>())
+            .collect::<Vec<_>>();
+        let targets = inputs.iter().map(|input| input.iter().sum::<f32>()).collect::<Vec<_>>();
+
+        let mut mlp = Mlp::new(&[3, 8], 1);
+        let loss = mlp.train(&inputs, &targets, 500, 0.01).unwrap();
+        assert!(loss < 0.01, "loss: {}", loss);
+        assert!((mlp.forward(&[1.0, 1.0, 0.0]) - 2.0).abs() < 0.2);
+    }
+
+    #[test]
+    fn test_training_checks_the_samples() {
+        let mut mlp = Mlp::new(&[2, 4], 1);
+        let trained = |mlp: &mut Mlp, inputs: &[Vec<f32>], targets: &[f32]| mlp.train(inputs, targets, 1, 0.01);
+        assert!(matches!(trained(&mut mlp, &[vec![0.0, 1.0]], &[1.0, 2.0]), Err(Error::Parse(_))));
+        assert!(matches!(trained(&mut mlp, &[vec![0.0, 1.0, 1.0]], &[1.0]), Err(Error::Parse(_))));
+        assert!(trained(&mut mlp, &[vec![0.0, 1.0]], &[1.0]).is_ok());
+        assert!(mlp.check_input_size(3).is_err());
+    }
+}
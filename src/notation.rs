@@ -0,0 +1,199 @@
+use crate::board::can_put_brick;
+use crate::brick::{xy, BrickId, XY};
+use crate::error::Error;
+use crate::game::{can_hold, Action, GameState, Move};
+
+// Moves are written as `brick_no position` - e.g. `3 d4` (1-based brick number, column letter
+// and row number), bricks are held with `hold brick_no` - e.g. `hold 2`.
+pub fn format_move(m: &Move) -> String {
+    format!("{} {}", m.brick_index + 1, format_position(&m.pos))
+}
+
+pub fn format_action(action: &Action) -> String {
+    match action {
+        Action::Place(m) => format_move(m),
+        Action::Hold { brick_index } => format!("hold {}", brick_index + 1),
+    }
+}
+
+// bricks are written as their IDs - e.g. `7r1 0r0 12r3`
+pub fn format_bricks(bricks: &[BrickId]) -> String {
+    bricks.iter().map(|brick| brick.to_string()).collect::<Vec<_>>().join(" ")
+}
+
+pub fn format_position(pos: &XY) -> String {
+    format!("{}{}", (b'a' + pos.x as u8) as char, pos.y + 1)
+}
+
+pub fn parse_position(text: &str, size: i32) -> Result<XY, Error> {
+    let last_letter = (b'a' + size as u8 - 1) as char;
+    let position_error = || {
+        Error::Parse(format!(
+            "second part of the input should contains a letter a-{} followed by a number 1-{} - e.g. `d3`",
+            last_letter, size
+        ))
+    };
+
+    let mut chars = text.chars();
+    let x = match chars.next() {
+        Some(c) if ('a'..=last_letter).contains(&c) => c as i32 - 'a' as i32,
+        _ => return Err(position_error()),
+    };
+    // `parse` alone would accept a sign, e.g. `d+3`
+    let row = chars.as_str();
+    if row.is_empty() || !row.bytes().all(|b| b.is_ascii_digit()) {
+        return Err(position_error());
+    }
+    let y = match row.parse::<i32>() {
+        Ok(i) if i > 0 && i <= size => i - 1,
+        _ => return Err(position_error()),
+    };
+
+    Ok(xy(x, y))
+}
+
+fn parse_brick_index(text: &str, game_state: &GameState) -> Result<i32, Error> {
+    let brick_count = game_state.available_bricks.len() as i32;
+    // `parse` alone would accept a sign, e.g. `+1`
+    if text.is_empty() || !text.bytes().all(|b| b.is_ascii_digit()) {
+        return Err(Error::Parse(format!("first value should be an integer: {}", text)));
+    }
+    match text.parse::<i32>() {
+        Ok(i) if i > 0 && i <= brick_count => Ok(i - 1), // translation from 1-based to 0-based indexing
+        Ok(..) => Err(Error::IllegalMove(format!("only bricks 1-{} are available", brick_count))),
+        Err(..) => Err(Error::Parse(format!("first value should be an integer: {}", text))),
+    }
+}
+
+// Parses an action and checks if it can be performed in the given state.
+pub fn parse_action(text: &str, game_state: &GameState) -> Result<Action, Error> {
+    let split = text.split_whitespace().collect::<Vec<_>>();
+    if split.len() != 2 {
+        return Err(Error::Parse(
+            "input should have 2 parts delimited with space - e.g. `1 d3`".to_string(),
+        ));
+    }
+
+    if split[0] == "hold" {
+        if !can_hold(game_state) {
+            return Err(Error::IllegalMove(
+                "you can't hold a brick now - it's allowed once between putting bricks on the board".to_string(),
+            ));
+        }
+        let brick_index = parse_brick_index(split[1], game_state)?;
+        return Ok(Action::Hold { brick_index });
+    }
+
+    let brick_index = parse_brick_index(split[0], game_state)?;
+    let pos = parse_position(split[1], game_state.board.size())?;
+    let brick = game_state.available_bricks[brick_index as usize].brick();
+    if !can_put_brick(&game_state.board, brick, &pos) {
+        return Err(Error::IllegalMove(format!(
+            "the brick ({}) can't be put in the position you selected ({})",
+            split[0], split[1]
+        )));
+    }
+
+    Ok(Action::Place(Move { brick_index, pos }))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::brick::brick_library;
+    use crate::game::{perform_action, Rules};
+    use proptest::prelude::*;
+
+    fn game_with_hand() -> GameState {
+        let mut game_state = GameState::with_rules(Rules {
+            hold: true,
+            ..Rules::default()
+        });
+        game_state.available_bricks = brick_library().ids().take(3).collect();
+        game_state
+    }
+
+    #[test]
+    fn test_format_and_parse_move() {
+        let game_state = game_with_hand();
+        let m = Move {
+            brick_index: 2,
+            pos: xy(3, 0),
+        };
+        assert_eq!(format_move(&m), "3 d1");
+
+        match parse_action("3 d1", &game_state) {
+            Ok(Action::Place(parsed)) => {
+                assert_eq!(parsed.brick_index, 2);
+                assert_eq!(parsed.pos, xy(3, 0));
+            }
+            other => panic!("unexpected action: {:?}", other),
+        }
+    }
+
+    #[test]
+    fn test_parse_hold() {
+        let game_state = game_with_hand();
+        assert!(matches!(
+            parse_action("hold 2", &game_state),
+            Ok(Action::Hold { brick_index: 1 })
+        ));
+        assert!(parse_action("hold 4", &game_state).is_err());
+        assert!(parse_action("hold 1", &GameState::new()).is_err());
+        assert!(matches!(parse_action("hold +2", &game_state), Err(Error::Parse(_))));
+    }
+
+    #[test]
+    fn test_parse_invalid_action() {
+        let game_state = game_with_hand();
+        assert!(parse_action("", &game_state).is_err());
+        assert!(parse_action("1", &game_state).is_err());
+        assert!(parse_action("0 a1", &game_state).is_err());
+        assert!(parse_action("x a1", &game_state).is_err());
+        assert!(parse_action("1 j1", &game_state).is_err());
+        assert!(parse_action("1 a10", &game_state).is_err());
+        assert!(parse_action("1 a1 b2", &game_state).is_err());
+        assert!(matches!(parse_action("4 a1", &game_state), Err(Error::IllegalMove(_))));
+        assert!(matches!(parse_action("1 a0", &game_state), Err(Error::Parse(_))));
+        assert!(matches!(parse_action("+1 d4", &game_state), Err(Error::Parse(_))));
+        assert!(matches!(parse_action("-1 d4", &game_state), Err(Error::Parse(_))));
+    }
+
+    #[test]
+    fn test_format_bricks() {
+        let bricks = [BrickId::new(7, 1).unwrap(), BrickId::new(0, 0).unwrap()];
+        assert_eq!(format_bricks(&bricks), "7r1 0r0");
+        assert_eq!(format_bricks(&[]), "");
+    }
+
+    #[test]
+    fn test_parse_position_on_big_board() {
+        assert_eq!(parse_position("p16", 16), Ok(xy(15, 15)));
+        assert!(parse_position("p16", 9).is_err());
+    }
+
+    #[test]
+    fn test_parse_position_accepts_only_digits() {
+        assert_eq!(parse_position("d3", 9), Ok(xy(3, 2)));
+        assert!(parse_position("d+3", 9).is_err());
+        assert!(parse_position("d-3", 9).is_err());
+        assert!(parse_position("d 3", 9).is_err());
+        assert!(parse_position("d", 9).is_err());
+    }
+
+    proptest! {
+        #[test]
+        fn test_format_and_parse_position(x in 0..16, y in 0..16) {
+            prop_assert_eq!(parse_position(&format_position(&xy(x, y)), 16), Ok(xy(x, y)));
+        }
+
+        // whatever is typed, parsed actions can be performed
+        #[test]
+        fn test_parsed_actions_are_legal(text in "(hold|[0-9]{1,2}) [a-z]?[0-9]{0,2}|.{0,10}") {
+            let game_state = game_with_hand();
+            if let Ok(action) = parse_action(&text, &game_state) {
+                prop_assert!(perform_action(&game_state, &action).is_ok());
+            }
+        }
+    }
+}
@@ -0,0 +1,210 @@
+use crate::brick::BrickId;
+use crate::error::Error;
+use crate::game::{Action, GameState};
+use crate::notation::parse_action;
+use std::io::{BufRead, BufReader, Write};
+use std::process::{Child, ChildStdin, Command, Stdio};
+use std::sync::mpsc::{channel, Receiver, RecvTimeoutError};
+use std::thread;
+use std::time::Duration;
+
+// External bots are child processes talking with the referee through stdin/stdout.
+//
+// Before every move the referee sends the position:
+//   board N                  - followed by N rows of cells: `.` empty, `X` filled, `#` stone, `@` double stone
+//   hand N                   - followed by N bricks, one per line
+//   next N                   - upcoming bricks (only when playing with preview), one per line
+//   held N                   - 0 or 1 bricks in the hold slot (only when holding is allowed)
+//   points P
+//   go
+// Bricks are written as their IDs (shape and rotation) followed by their cells' offsets -
+// e.g. `10r0 0,0 1,0 1,1 2,1`.
+// The bot answers with a single line - a move in the standard notation (e.g. `3 d4`) or `hold 2`.
+// When the game ends the referee sends `gameover P` and closes the bot's stdin.
+pub struct ExternalBot {
+    child: Child,
+    // closed when the game is over, so the bot sees the end of its input
+    stdin: Option<ChildStdin>,
+    lines: Receiver<String>,
+}
+
+impl ExternalBot {
+    // the command is run by the shell, so it may contain arguments
+    pub fn spawn(command: &str) -> Result<ExternalBot, Error> {
+        let mut child = Command::new("sh")
+            .arg("-c")
+            .arg(command)
+            .stdin(Stdio::piped())
+            .stdout(Stdio::piped())
+            .spawn()
+            .map_err(|e| Error::Io(format!("can't start bot `{}`: {}", command, e)))?;
+        let stdin = child.stdin.take().unwrap();
+        let stdout = child.stdout.take().unwrap();
+
+        // reading in a separate thread makes it possible to stop waiting for an answer
+        let (sender, lines) = channel();
+        thread::spawn(move || {
+            for line in BufReader::new(stdout).lines().map_while(Result::ok) {
+                if sender.send(line).is_err() {
+                    break;
+                }
+            }
+        });
+
+        Ok(ExternalBot {
+            child,
+            stdin: Some(stdin),
+            lines,
+        })
+    }
+
+    // Asks the bot for its action. Answers which can't be parsed and illegal moves are
+    // `IllegalMove` errors, answers which didn't come within the time limit are `Io` errors.
+    pub fn request_action(&mut self, game_state: &GameState, time_limit: Duration) -> Result<Action, Error> {
+        self.send(&format_position(game_state))?;
+
+        let answer = match self.lines.recv_timeout(time_limit) {
+            Ok(answer) => answer,
+            Err(RecvTimeoutError::Timeout) => {
+                return Err(Error::Io(format!("no answer within {} ms", time_limit.as_millis())))
+            }
+            Err(RecvTimeoutError::Disconnected) => return Err(Error::Io("the bot closed its output".to_string())),
+        };
+
+        parse_action(answer.trim(), game_state).map_err(|e| Error::IllegalMove(format!("`{}`: {}", answer.trim(), e)))
+    }
+
+    pub fn finish(mut self, game_state: &GameState) {
+        // the bot might have quit already
+        let _ = self.send(&format!("gameover {}\n", game_state.points));
+        self.stdin = None;
+
+        // give the bot a moment to quit on its own, otherwise it's killed when dropped
+        for _ in 0..10 {
+            if let Ok(Some(_)) = self.child.try_wait() {
+                return;
+            }
+            thread::sleep(Duration::from_millis(100));
+        }
+    }
+
+    fn send(&mut self, text: &str) -> Result<(), Error> {
+        let stdin = self.stdin.as_mut().ok_or_else(|| Error::Io("the bot's input is closed".to_string()))?;
+        stdin
+            .write_all(text.as_bytes())
+            .and_then(|_| stdin.flush())
+            .map_err(|e| Error::Io(format!("can't send the position to the bot: {}", e)))
+    }
+}
+
+// also when a game ends early with an error, so no bot is left running
+impl Drop for ExternalBot {
+    fn drop(&mut self) {
+        self.stdin = None;
+        let _ = self.child.kill();
+        let _ = self.child.wait();
+    }
+}
+
+fn format_brick(id: BrickId) -> String {
+    let mut text = id.to_string();
+    for v in id.brick().offsets() {
+        text += &format!(" {},{}", v.x, v.y);
+    }
+    text
+}
+
+fn format_bricks(name: &str, bricks: &[BrickId]) -> String {
+    let mut text = format!("{} {}\n", name, bricks.len());
+    for &brick in bricks {
+        text += &format_brick(brick);
+        text += "\n";
+    }
+    text
+}
+
+pub fn format_position(game_state: &GameState) -> String {
+    let mut text = format!("board {}\n", game_state.board.size());
+    for row in game_state.board.rows() {
+        text.extend(row.iter().map(|cell| cell.symbol()));
+        text.push('\n');
+    }
+
+    text += &format_bricks("hand", &game_state.available_bricks);
+    if !game_state.upcoming_bricks.is_empty() {
+        text += &format_bricks("next", &game_state.upcoming_bricks);
+    }
+    if game_state.rules.hold {
+        let held = game_state.held_brick.iter().copied().collect::<Vec<_>>();
+        text += &format_bricks("held", &held);
+    }
+    text += &format!("points {}\ngo\n", game_state.points);
+    text
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::brick::xy;
+    use crate::game::Move;
+    use crate::Cell;
+
+    fn game_with_hand() -> GameState {
+        let mut game_state = GameState::new();
+        game_state.board.set(xy(0, 0), Cell::Stone);
+        game_state.available_bricks = vec![BrickId::new(1, 0).unwrap()];
+        game_state
+    }
+
+    #[test]
+    fn test_format_position() {
+        let text = format_position(&game_with_hand());
+        let lines = text.lines().collect::<Vec<_>>();
+        assert_eq!(lines[0], "board 9");
+        assert_eq!(lines[1], "#........");
+        assert_eq!(lines[10], "hand 1");
+        assert_eq!(lines[11], "1r0 0,0 1,0");
+        assert_eq!(lines[12], "points 0");
+        assert_eq!(lines[13], "go");
+    }
+
+    #[test]
+    fn test_external_bot_move() {
+        let mut bot = ExternalBot::spawn("read line; echo '1 b1'; cat > /dev/null").unwrap();
+        let game_state = game_with_hand();
+        match bot.request_action(&game_state, Duration::from_secs(5)) {
+            Ok(Action::Place(Move { brick_index, pos })) => {
+                assert_eq!(brick_index, 0);
+                assert_eq!(pos, xy(1, 0));
+            }
+            other => panic!("unexpected action: {:?}", other),
+        }
+        bot.finish(&game_state);
+    }
+
+    #[test]
+    fn test_external_bot_illegal_move() {
+        // the stone is in the way
+        let mut bot = ExternalBot::spawn("echo '1 a1'; cat > /dev/null").unwrap();
+        let game_state = game_with_hand();
+        assert!(matches!(bot.request_action(&game_state, Duration::from_secs(5)), Err(Error::IllegalMove(_))));
+        bot.finish(&game_state);
+    }
+
+    #[test]
+    fn test_external_bot_timeout() {
+        let mut bot = ExternalBot::spawn("cat > /dev/null").unwrap();
+        let game_state = game_with_hand();
+        assert!(matches!(bot.request_action(&game_state, Duration::from_millis(50)), Err(Error::Io(_))));
+        bot.finish(&game_state);
+    }
+
+    #[test]
+    fn test_external_bot_is_killed_when_dropped() {
+        let bot = ExternalBot::spawn("sleep 60").unwrap();
+        let id = bot.child.id();
+        drop(bot);
+        // the process has been killed and reaped
+        assert!(!std::path::Path::new(&format!("/proc/{}", id)).exists());
+    }
+}
@@ -1,105 +1,57 @@
-use crate::Board;
+use crate::brick::XY;
+use crate::geometry::REGION_KINDS;
+use crate::{Board, Cell};
 
 pub struct ResolveResult {
     pub board: Board,
     pub blocks_removed: i32,
+    // indexed by `RegionKind`
+    pub removed_by_kind: [i32; REGION_KINDS.len()],
 }
 
 pub fn resolve_board(board: Board) -> ResolveResult {
-    let mut resolved_board = board.clone();
+    let mut resolved_board = board;
     let mut blocks_removed = 0;
+    let mut removed_by_kind = [0; REGION_KINDS.len()];
 
-    blocks_removed = blocks_removed + resolve_rows(board, &mut resolved_board);
-    blocks_removed = blocks_removed + resolve_columns(board, &mut resolved_board);
-    blocks_removed = blocks_removed + resolve_blocks(board, &mut resolved_board);
+    for region in &board.geometry().regions {
+        if resolve_region(board, &mut resolved_board, &region.cells) {
+            blocks_removed += 1;
+            removed_by_kind[region.kind as usize] += 1;
+        }
+    }
 
     ResolveResult {
         board: resolved_board,
         blocks_removed,
+        removed_by_kind,
     }
 }
 
-fn resolve_rows(base_board: Board, resolved_board: &mut Board) -> i32 {
-    let mut blocks_removed = 0;
-
-    for y in 0..9 {
-        let mut full_row = true;
-        for x in 0..9 {
-            if !base_board[x][y] {
-                full_row = false;
-                break;
-            }
-        }
-
-        if full_row {
-            blocks_removed = blocks_removed + 1;
-
-            for x in 0..9 {
-                resolved_board[x][y] = false;
-            }
-        }
+// Full regions are checked on the base board, so overlapping regions (e.g. a row and a column)
+// can be cleared by the same move. A region is cleared only when the player put something in it,
+// so regions made only of stones and double stones stay.
+fn resolve_region(base_board: Board, resolved_board: &mut Board, region: &[XY]) -> bool {
+    let full = region.iter().all(|&v| base_board.get(v).is_filled());
+    let any_filled = region.iter().any(|&v| base_board.get(v) == Cell::Filled);
+    if !full || !any_filled {
+        return false;
     }
 
-    blocks_removed
-}
-
-fn resolve_columns(base_board: Board, resolved_board: &mut Board) -> i32 {
-    let mut blocks_removed = 0;
-
-    for x in 0..9 {
-        let mut full_col = true;
-        for y in 0..9 {
-            if !base_board[x][y] {
-                full_col = false;
-                break;
-            }
-        }
-
-        if full_col {
-            blocks_removed = blocks_removed + 1;
-
-            for y in 0..9 {
-                resolved_board[x][y] = false;
-            }
-        }
-    }
-
-    blocks_removed
-}
-
-fn resolve_blocks(base_board: Board, resolved_board: &mut Board) -> i32 {
-    let mut blocks_removed = 0;
-
-    for block_x in 0..3 {
-        for block_y in 0..3 {
-            let mut full_block = true;
-            for x in 0..3 {
-                for y in 0..3 {
-                    if !base_board[block_x * 3 + x][block_y * 3 + y] {
-                        full_block = false;
-                        break;
-                    }
-                }
-            }
-
-            if full_block {
-                blocks_removed = blocks_removed + 1;
-
-                for x in 0..3 {
-                    for y in 0..3 {
-                        resolved_board[block_x * 3 + x][block_y * 3 + y] = false;
-                    }
-                }
-            }
-        }
+    for &v in region {
+        resolved_board.set(v, resolved_board.get(v).cleared());
     }
 
-    blocks_removed
+    true
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::brick::xy;
+    use crate::geometry::{parse_regions, Geometry, RegionKind};
+    use crate::testing;
+    use proptest::prelude::*;
 
     #[test]
     fn test_resolve_empty_board() {
@@ -108,7 +60,7 @@ mod tests {
 
         for x in 0..9 {
             for y in 0..9 {
-                assert_eq!(ret.board[x][y], false);
+                assert_eq!(ret.board.get(xy(x, y)), Cell::Empty);
             }
         }
     }
@@ -117,13 +69,13 @@ mod tests {
     fn test_resolve_row() {
         let mut board = Board::default();
         for x in 0..9 {
-            board[x][4] = true;
+            board.set(xy(x, 4), Cell::Filled);
         }
 
         let ret = resolve_board(board);
         assert_eq!(ret.blocks_removed, 1);
         for x in 0..9 {
-            assert_eq!(ret.board[x][4], false);
+            assert_eq!(ret.board.get(xy(x, 4)), Cell::Empty);
         }
     }
 
@@ -131,15 +83,15 @@ mod tests {
     fn test_resolve_2_rows() {
         let mut board = Board::default();
         for x in 0..9 {
-            board[x][4] = true;
-            board[x][7] = true;
+            board.set(xy(x, 4), Cell::Filled);
+            board.set(xy(x, 7), Cell::Filled);
         }
 
         let ret = resolve_board(board);
         assert_eq!(ret.blocks_removed, 2);
         for x in 0..9 {
-            assert_eq!(ret.board[x][4], false);
-            assert_eq!(ret.board[x][7], false);
+            assert_eq!(ret.board.get(xy(x, 4)), Cell::Empty);
+            assert_eq!(ret.board.get(xy(x, 7)), Cell::Empty);
         }
     }
 
@@ -147,13 +99,13 @@ mod tests {
     fn test_resolve_column() {
         let mut board = Board::default();
         for y in 0..9 {
-            board[3][y] = true;
+            board.set(xy(3, y), Cell::Filled);
         }
 
         let ret = resolve_board(board);
         assert_eq!(ret.blocks_removed, 1);
         for y in 0..9 {
-            assert_eq!(ret.board[3][y], false);
+            assert_eq!(ret.board.get(xy(3, y)), Cell::Empty);
         }
     }
 
@@ -161,15 +113,15 @@ mod tests {
     fn test_resolve_2_columns() {
         let mut board = Board::default();
         for y in 0..9 {
-            board[3][y] = true;
-            board[5][y] = true;
+            board.set(xy(3, y), Cell::Filled);
+            board.set(xy(5, y), Cell::Filled);
         }
 
         let ret = resolve_board(board);
         assert_eq!(ret.blocks_removed, 2);
         for y in 0..9 {
-            assert_eq!(ret.board[3][y], false);
-            assert_eq!(ret.board[5][y], false);
+            assert_eq!(ret.board.get(xy(3, y)), Cell::Empty);
+            assert_eq!(ret.board.get(xy(5, y)), Cell::Empty);
         }
     }
 
@@ -178,7 +130,7 @@ mod tests {
         let mut board = Board::default();
         for x in 3..6 {
             for y in 3..6 {
-                board[x][y] = true;
+                board.set(xy(x, y), Cell::Filled);
             }
         }
 
@@ -186,7 +138,7 @@ mod tests {
         assert_eq!(ret.blocks_removed, 1);
         for x in 3..6 {
             for y in 3..6 {
-                assert_eq!(ret.board[x][y], false);
+                assert_eq!(ret.board.get(xy(x, y)), Cell::Empty);
             }
         }
     }
@@ -196,24 +148,241 @@ mod tests {
         let mut board = Board::default();
         for x in 3..6 {
             for y in 3..6 {
-                board[x][y] = true;
+                board.set(xy(x, y), Cell::Filled);
             }
         }
 
         for x in 0..9 {
-            board[x][2] = true;
+            board.set(xy(x, 2), Cell::Filled);
         }
 
         for y in 0..9 {
-            board[1][y] = true;
+            board.set(xy(1, y), Cell::Filled);
         }
 
         let ret = resolve_board(board);
         assert_eq!(ret.blocks_removed, 3);
         for x in 0..9 {
             for y in 0..9 {
-                assert_eq!(ret.board[x][y], false);
+                assert_eq!(ret.board.get(xy(x, y)), Cell::Empty);
+            }
+        }
+    }
+
+    #[test]
+    fn test_stone_stays_after_clear() {
+        let mut board = Board::default();
+        for x in 0..9 {
+            board.set(xy(x, 4), Cell::Filled);
+        }
+        board.set(xy(2, 4), Cell::Stone);
+
+        let ret = resolve_board(board);
+        assert_eq!(ret.blocks_removed, 1);
+        for x in 0..9 {
+            let expected = if x == 2 { Cell::Stone } else { Cell::Empty };
+            assert_eq!(ret.board.get(xy(x, 4)), expected);
+        }
+    }
+
+    #[test]
+    fn test_double_stone_needs_2_clears() {
+        let mut board = Board::default();
+        for x in 0..9 {
+            board.set(xy(x, 4), Cell::Filled);
+        }
+        board.set(xy(2, 4), Cell::DoubleStone);
+
+        let ret = resolve_board(board);
+        assert_eq!(ret.blocks_removed, 1);
+        assert_eq!(ret.board.get(xy(2, 4)), Cell::Filled);
+
+        // clearing the row and the column at once counts as 2 clears
+        let mut board = Board::default();
+        for i in 0..9 {
+            board.set(xy(i, 4), Cell::Filled);
+            board.set(xy(2, i), Cell::Filled);
+        }
+        board.set(xy(2, 4), Cell::DoubleStone);
+
+        let ret = resolve_board(board);
+        assert_eq!(ret.blocks_removed, 2);
+        assert_eq!(ret.board.get(xy(2, 4)), Cell::Empty);
+    }
+
+    #[test]
+    fn test_line_of_stones_is_not_cleared() {
+        let mut board = Board::default();
+        for x in 0..9 {
+            board.set(xy(x, 0), Cell::Stone);
+        }
+
+        let ret = resolve_board(board);
+        assert_eq!(ret.blocks_removed, 0);
+        assert_eq!(ret.board, board);
+    }
+
+    #[test]
+    fn test_line_of_stones_and_double_stones_is_not_cleared() {
+        let mut board = Board::default();
+        for x in 0..9 {
+            board.set(xy(x, 0), if x % 2 == 0 { Cell::Stone } else { Cell::DoubleStone });
+            board.set(xy(x, 8), Cell::DoubleStone);
+        }
+
+        let ret = resolve_board(board);
+        assert_eq!(ret.blocks_removed, 0);
+        assert_eq!(ret.board, board);
+
+        // one cell put by the player is enough
+        board.set(xy(0, 0), Cell::Filled);
+        let ret = resolve_board(board);
+        assert_eq!(ret.blocks_removed, 1);
+        assert_eq!(ret.board.get(xy(0, 0)), Cell::Empty);
+        assert_eq!(ret.board.get(xy(1, 0)), Cell::Filled);
+        assert_eq!(ret.board.get(xy(2, 0)), Cell::Stone);
+    }
+
+    #[test]
+    fn test_resolve_board_without_boxes() {
+        let geometry = Geometry::new(8, 0).unwrap().leak();
+        let mut board = Board::new(geometry);
+        for x in 0..4 {
+            for y in 0..4 {
+                board.set(xy(x, y), Cell::Filled);
+            }
+        }
+        for x in 0..8 {
+            board.set(xy(x, 6), Cell::Filled);
+        }
+
+        let ret = resolve_board(board);
+        assert_eq!(ret.blocks_removed, 1);
+        assert_eq!(ret.board.get(xy(0, 0)), Cell::Filled);
+        assert_eq!(ret.board.get(xy(0, 6)), Cell::Empty);
+    }
+
+    #[test]
+    fn test_resolve_big_box() {
+        let geometry = Geometry::new(16, 4).unwrap().leak();
+        let mut board = Board::new(geometry);
+        for x in 12..16 {
+            for y in 12..16 {
+                board.set(xy(x, y), Cell::Filled);
+            }
+        }
+
+        let ret = resolve_board(board);
+        assert_eq!(ret.blocks_removed, 1);
+        assert_eq!(ret.board, Board::new(geometry));
+    }
+
+    #[test]
+    fn test_resolve_diagonal() {
+        let geometry = parse_regions("diagonals", 9, 3).unwrap().leak();
+        let mut board = Board::new(geometry);
+        for i in 0..9 {
+            board.set(xy(8 - i, i), Cell::Filled);
+        }
+
+        let ret = resolve_board(board);
+        assert_eq!(ret.blocks_removed, 1);
+        assert_eq!(ret.removed_by_kind[RegionKind::Diagonal as usize], 1);
+        assert_eq!(ret.board, Board::new(geometry));
+    }
+
+    #[test]
+    fn test_resolve_jigsaw_box() {
+        let geometry = parse_regions("aabb\nacbb\naccd\n.ddd", 4, 2).unwrap().leak();
+        let mut board = Board::new(geometry);
+        board.set(xy(1, 1), Cell::Filled);
+        board.set(xy(1, 2), Cell::Filled);
+        board.set(xy(2, 2), Cell::Filled);
+
+        let ret = resolve_board(board);
+        assert_eq!(ret.blocks_removed, 1);
+        assert_eq!(ret.removed_by_kind[RegionKind::Box as usize], 1);
+        assert_eq!(ret.board, Board::new(geometry));
+    }
+
+    // Independent bitboard implementation of the rules - a cell of a region is a bit of its mask.
+    mod bitboard {
+        use super::*;
+
+        type Bits = [u64; 4];
+
+        fn mask(board: &Board, cells: &[XY]) -> Bits {
+            let mut bits = [0; 4];
+            for v in cells {
+                let i = (v.y * board.size() + v.x) as usize;
+                bits[i / 64] |= 1 << (i % 64);
+            }
+            bits
+        }
+
+        fn cells_where(board: &Board, predicate: impl Fn(Cell) -> bool) -> Bits {
+            let cells = board.positions().filter(|&v| predicate(board.get(v))).collect::<Vec<_>>();
+            mask(board, &cells)
+        }
+
+        fn is_subset(a: &Bits, b: &Bits) -> bool {
+            a.iter().zip(b).all(|(a, b)| a & !b == 0)
+        }
+
+        pub fn resolve(board: &Board) -> (Board, i32) {
+            let filled = cells_where(board, Cell::is_filled);
+            let plain = cells_where(board, |cell| cell == Cell::Filled);
+            // cells of cleared regions, and cells of at least 2 cleared regions
+            let (mut cleared, mut cleared_twice) = ([0u64; 4], [0u64; 4]);
+            let mut blocks_removed = 0;
+            for region in &board.geometry().regions {
+                let region_mask = mask(board, &region.cells);
+                let any_plain = region_mask.iter().zip(&plain).any(|(r, p)| r & p != 0);
+                if is_subset(&region_mask, &filled) && any_plain {
+                    for i in 0..4 {
+                        cleared_twice[i] |= cleared[i] & region_mask[i];
+                        cleared[i] |= region_mask[i];
+                    }
+                    blocks_removed += 1;
+                }
+            }
+
+            let mut resolved = *board;
+            for (v, cell) in board.cells() {
+                let i = (v.y * board.size() + v.x) as usize;
+                let bit = |bits: &Bits| bits[i / 64] & (1 << (i % 64)) != 0;
+                let resolved_cell = match (cell, bit(&cleared), bit(&cleared_twice)) {
+                    (cell, false, _) => cell,
+                    (Cell::Stone, true, _) => Cell::Stone,
+                    (Cell::DoubleStone, true, false) => Cell::Filled,
+                    (_, true, _) => Cell::Empty,
+                };
+                resolved.set(v, resolved_cell);
+            }
+            (resolved, blocks_removed)
+        }
+    }
+
+    proptest! {
+        #[test]
+        fn test_resolve_agrees_with_bitboards(board in testing::board()) {
+            let ret = resolve_board(board);
+            let (expected_board, expected_blocks_removed) = bitboard::resolve(&board);
+            prop_assert_eq!(ret.board, expected_board);
+            prop_assert_eq!(ret.blocks_removed, expected_blocks_removed);
+        }
+
+        #[test]
+        fn test_resolve_never_adds_cells(board in testing::board()) {
+            let ret = resolve_board(board);
+            for (row, resolved_row) in board.rows().zip(ret.board.rows()) {
+                for (cell, resolved_cell) in row.iter().zip(resolved_row) {
+                    prop_assert!(!resolved_cell.is_filled() || cell.is_filled());
+                    // stones stay where they are
+                    prop_assert!(*cell != Cell::Stone || *resolved_cell == Cell::Stone);
+                }
             }
+            prop_assert_eq!(ret.removed_by_kind.iter().sum::<i32>(), ret.blocks_removed);
         }
     }
 }
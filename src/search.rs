@@ -0,0 +1,601 @@
+use crate::ai::{
+    evaluate_occupancy, reference_search, reference_submove_score, Evaluator, PossibleMoveScore, MAX_SEARCH_DEPTH,
+};
+use crate::board::can_put_brick;
+use crate::brick::{BrickId, XY};
+use crate::error::Error;
+use crate::game::{GameState, PossibleMove, RefillPolicy, MAX_HAND_SIZE};
+use crate::geometry::{Geometry, MAX_BOARD_SIZE};
+use crate::metrics::{self, Counter};
+use crate::{Board, Cell, Occupancy};
+use rayon::prelude::*;
+
+// The bot's search. Positions are small `Copy` values living on the stack - the board as
+// bitboards, the bricks left from the hand (as bits) and the points - instead of cloned
+// `GameState`s, and moves are generated lazily from the bricks' placement data, so nothing is
+// allocated below the root. Only the moves at the root are searched in parallel (if at all, see
+// `Parallelism`).
+//
+// The scores (and the chosen moves) are exactly the ones of the original search, which is kept
+// in `ai.rs` as the reference. `examples/search_speedup.rs` compares the speed of both.
+
+// How a search uses the threads of rayon's pool - its size is set by the caller, e.g. with
+// `rayon::ThreadPoolBuilder::build_global`.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum Parallelism {
+    // the moves at the root are searched in parallel - the moves right after the scored one
+    // when scoring a single move
+    Root,
+    // everything on the calling thread - when games are played in parallel instead
+    Off,
+}
+
+// a position which can't be saved anymore
+const DEAD_PENALTY: i32 = 1000;
+
+// A board as bitboards, one bit per cell like `Occupancy`.
+#[derive(Copy, Clone)]
+struct Cells {
+    // every cell which isn't empty, stones included
+    filled: Occupancy,
+    stones: Occupancy,
+    double_stones: Occupancy,
+}
+
+impl Cells {
+    fn new(board: &Board) -> Cells {
+        let mut cells = Cells {
+            filled: Occupancy::default(),
+            stones: Occupancy::default(),
+            double_stones: Occupancy::default(),
+        };
+        for (pos, cell) in board.cells() {
+            let (x, y) = (pos.x as usize, pos.y as usize);
+            match cell {
+                Cell::Empty => continue,
+                Cell::Filled => {}
+                Cell::Stone => cells.stones[y] |= 1 << x,
+                Cell::DoubleStone => cells.double_stones[y] |= 1 << x,
+            }
+            cells.filled[y] |= 1 << x;
+        }
+        cells
+    }
+
+    fn board(&self, geometry: &'static Geometry) -> Board {
+        let mut board = Board::new(geometry);
+        for pos in Board::new(geometry).positions() {
+            let bit = |rows: &Occupancy| rows[pos.y as usize] >> pos.x & 1 != 0;
+            let cell = if bit(&self.stones) {
+                Cell::Stone
+            } else if bit(&self.double_stones) {
+                Cell::DoubleStone
+            } else if bit(&self.filled) {
+                Cell::Filled
+            } else {
+                continue;
+            };
+            board.set(pos, cell);
+        }
+        board
+    }
+
+    // `resolve_board` - returns the number of cleared regions
+    fn resolve(&mut self, regions: &[Occupancy]) -> i32 {
+        let covers = |rows: &Occupancy, region: &Occupancy| rows.iter().zip(region).all(|(row, r)| row & r == *r);
+        let mut cleared = Occupancy::default();
+        let mut cleared_twice = Occupancy::default();
+        let mut blocks_removed = 0;
+        let mut plain = self.filled;
+        for (row, (stones, double_stones)) in plain.iter_mut().zip(self.stones.iter().zip(&self.double_stones)) {
+            *row &= !stones & !double_stones;
+        }
+        for region in regions {
+            // regions without any cell put by the player aren't cleared
+            let any_plain = plain.iter().zip(region).any(|(row, r)| row & r != 0);
+            if covers(&self.filled, region) && any_plain {
+                for y in 0..MAX_BOARD_SIZE as usize {
+                    cleared_twice[y] |= cleared[y] & region[y];
+                    cleared[y] |= region[y];
+                }
+                blocks_removed += 1;
+            }
+        }
+        if blocks_removed > 0 {
+            for y in 0..MAX_BOARD_SIZE as usize {
+                // stones stay, double stones turn into regular filled cells - which are cleared
+                // too by a second region
+                self.filled[y] &= !(cleared[y] & plain[y]) & !(cleared_twice[y] & self.double_stones[y]);
+                self.double_stones[y] &= !cleared[y];
+            }
+        }
+        blocks_removed
+    }
+}
+
+#[derive(Copy, Clone)]
+struct Position {
+    cells: Cells,
+    // bit `i` is set when the brick `i` of the hand at the root isn't used yet
+    hand: u32,
+    points: i32,
+    last_move_was_match: bool,
+}
+
+// Moves of the bricks whose bits are set in `hand`, generated lazily.
+fn moves(bricks: &[BrickId], size: i32, filled: Occupancy, hand: u32) -> impl Iterator<Item = (usize, XY)> + '_ {
+    (0..bricks.len())
+        .filter(move |i| hand & 1 << i != 0)
+        .flat_map(move |i| {
+            metrics::inc(Counter::PossibleMoves);
+            let brick = bricks[i].brick();
+            brick
+                .anchors(size)
+                .filter(move |&pos| brick.fits(&filled, pos))
+                .map(move |pos| (i, pos))
+        })
+}
+
+// what stays the same in the whole search
+struct Search<'a> {
+    geometry: &'static Geometry,
+    // the cells of every region of the geometry
+    regions: &'static [Occupancy],
+    hand: &'a [BrickId],
+    // the beginning of the next hand - known when playing with preview
+    next_hand: &'a [BrickId],
+    refill_policy: RefillPolicy,
+    evaluator: &'a Evaluator,
+}
+
+impl Search<'_> {
+    fn new<'a>(game_state: &'a GameState, evaluator: &'a Evaluator) -> Option<(Search<'a>, Position)> {
+        let hand = &game_state.available_bricks[..];
+        if hand.len() > MAX_HAND_SIZE {
+            return None;
+        }
+        let geometry = game_state.board.geometry();
+        let next_hand_size = std::cmp::min(game_state.rules.hand_size, game_state.upcoming_bricks.len());
+        let search = Search {
+            geometry,
+            regions: geometry.region_masks(),
+            hand,
+            next_hand: &game_state.upcoming_bricks[..next_hand_size],
+            refill_policy: game_state.rules.refill_policy,
+            evaluator,
+        };
+        let root = Position {
+            cells: Cells::new(&game_state.board),
+            hand: (1 << hand.len()) - 1,
+            points: game_state.points,
+            last_move_was_match: game_state.last_move_was_match,
+        };
+        Some((search, root))
+    }
+
+    // bricks in the order of the hand, then like `possible_moves` - the order decides between
+    // moves with the same score
+    fn moves<'a>(&'a self, cells: &Cells, hand: u32) -> impl Iterator<Item = (usize, XY)> + 'a {
+        moves(self.hand, self.geometry.size, cells.filled, hand)
+    }
+
+    // `perform_move` and its points
+    fn play(&self, position: &Position, brick_index: usize, pos: XY) -> Position {
+        metrics::inc(Counter::PutBrick);
+        let mut cells = position.cells;
+        self.hand[brick_index].brick().fill(&mut cells.filled, pos);
+        let blocks_removed = cells.resolve(self.regions);
+
+        let streak = position.last_move_was_match && blocks_removed > 0;
+        let newly_filled = cells
+            .filled
+            .iter()
+            .zip(&position.cells.filled)
+            .map(|(new, old)| (new & !old).count_ones() as i32)
+            .sum::<i32>();
+        Position {
+            cells,
+            hand: position.hand & !(1 << brick_index),
+            points: position.points + blocks_removed * 18 + if streak { 9 } else { 0 } + newly_filled,
+            last_move_was_match: blocks_removed > 0,
+        }
+    }
+
+    // `game::is_dead` for the given bricks
+    fn is_dead(&self, cells: &Cells, bricks: &[BrickId]) -> bool {
+        match self.refill_policy {
+            RefillPolicy::WhenEmpty => {
+                let hand = (1 << bricks.len()) - 1;
+                !self.fits_greedily(cells, bricks) && !self.fully_placeable(cells, bricks, hand)
+            }
+            RefillPolicy::AfterEveryMove => {
+                let hand = (1 << bricks.len()) - 1;
+                !bricks.is_empty() && moves(bricks, self.geometry.size, cells.filled, hand).next().is_none()
+            }
+        }
+    }
+
+    // `hand_is_fully_placeable` for the bricks whose bits are set in `hand`, on the stack: every
+    // order and spot of the bricks, depth first, the most constrained bricks first and with the
+    // same pruning of stuck bricks - but without remembering dead positions
+    fn fully_placeable(&self, cells: &Cells, bricks: &[BrickId], hand: u32) -> bool {
+        if hand == 0 {
+            return true;
+        }
+
+        let size = self.geometry.size;
+        let spots = |i: usize| {
+            let brick = bricks[i].brick();
+            brick.anchors(size).filter(move |&pos| brick.fits(&cells.filled, pos))
+        };
+        // (number of spots, brick)
+        let mut order = [(0, 0); MAX_HAND_SIZE];
+        let mut count = 0;
+        let mut placeable_cells = 0;
+        for i in (0..bricks.len()).filter(|i| hand & 1 << i != 0) {
+            let spot_count = spots(i).count();
+            if spot_count > 0 {
+                placeable_cells += bricks[i].brick().offsets().len();
+            }
+            order[count] = (spot_count, i);
+            count += 1;
+        }
+        let order = &mut order[..count];
+
+        // the last brick only needs a single spot
+        if count == 1 {
+            return order[0].0 > 0;
+        }
+        // a stuck brick needs a clear first (see `feasibility::stuck_for_good`)
+        if order.iter().any(|&(spot_count, _)| spot_count == 0) && placeable_cells < self.fewest_empty_cells(cells) {
+            return false;
+        }
+
+        order.sort_unstable();
+        for (k, &(_, i)) in order.iter().enumerate() {
+            // the same brick twice in the hand is tried just once
+            if order[..k].iter().any(|&(_, j)| bricks[j] == bricks[i]) {
+                continue;
+            }
+            let brick = bricks[i].brick();
+            for pos in spots(i) {
+                let mut next = *cells;
+                brick.fill(&mut next.filled, pos);
+                next.resolve(self.regions);
+                if self.fully_placeable(&next, bricks, hand & !(1 << i)) {
+                    return true;
+                }
+            }
+        }
+        false
+    }
+
+    // cells missing in the region closest to being cleared, `usize::MAX` if no region can be
+    fn fewest_empty_cells(&self, cells: &Cells) -> usize {
+        let covers = |rows: &Occupancy, region: &Occupancy| rows.iter().zip(region).all(|(row, r)| row & r == *r);
+        self.regions
+            .iter()
+            // regions of stones are never cleared
+            .filter(|region| !covers(&cells.stones, region))
+            .map(|region| {
+                region
+                    .iter()
+                    .zip(&cells.filled)
+                    .map(|(r, row)| (r & !row).count_ones() as usize)
+                    .sum::<usize>()
+            })
+            .min()
+            .unwrap_or(usize::MAX)
+    }
+
+    // puts the bricks one by one in the first spot where they fit - most of the time that's
+    // enough to tell the bricks can all be placed
+    fn fits_greedily(&self, cells: &Cells, bricks: &[BrickId]) -> bool {
+        let mut cells = *cells;
+        for brick in bricks.iter().map(|id| id.brick()) {
+            match brick
+                .anchors(self.geometry.size)
+                .find(|&pos| brick.fits(&cells.filled, pos))
+            {
+                Some(pos) => {
+                    brick.fill(&mut cells.filled, pos);
+                    cells.resolve(self.regions);
+                }
+                None => return false,
+            }
+        }
+        true
+    }
+
+    // Networks score the leaves one by one too - `Mlp::forward_batch` only spreads the leaves over
+    // threads, which the root moves already are (`examples/search_speedup.rs` compares both).
+    fn leaf(&self, position: &Position, depth: usize) -> i32 {
+        metrics::inc(Counter::SearchLeaves);
+        metrics::add(Counter::SearchLeafDepth, (MAX_SEARCH_DEPTH + 1 - depth) as u64);
+        match self.evaluator {
+            Evaluator::Heuristic => {
+                evaluate_occupancy(&position.cells.filled, self.geometry.size as usize, position.points)
+            }
+            evaluator => evaluator.evaluate_board(&position.cells.board(self.geometry), position.points),
+        }
+    }
+
+    // `parallelism` of the search after the move, deeper searches are always on a single thread
+    fn submove(&self, position: &Position, brick_index: usize, pos: XY, depth: usize, parallelism: Parallelism) -> i32 {
+        metrics::inc(Counter::SearchNodes);
+        let s = self.play(position, brick_index, pos);
+        if s.hand == 0 {
+            if !self.next_hand.is_empty() && self.is_dead(&s.cells, self.next_hand) {
+                return s.points - DEAD_PENALTY;
+            }
+            return self.leaf(&s, depth);
+        }
+
+        // dead positions are cut off before searching through all the ways of failing
+        let mut bricks = [self.hand[0]; MAX_HAND_SIZE];
+        let mut count = 0;
+        for i in (0..self.hand.len()).filter(|i| s.hand & 1 << i != 0) {
+            bricks[count] = self.hand[i];
+            count += 1;
+        }
+        if self.is_dead(&s.cells, &bricks[..count]) {
+            return s.points - DEAD_PENALTY;
+        }
+
+        if depth <= 1 {
+            return self.leaf(&s, depth);
+        }
+
+        // positions which aren't dead always have some moves
+        let best = match parallelism {
+            Parallelism::Root => self.parallel_search(&s, depth - 1),
+            Parallelism::Off => self.search(&s, depth - 1),
+        };
+        best.unwrap_or(s.points - DEAD_PENALTY)
+    }
+
+    // the best score of the moves, `None` if there are no moves
+    fn search(&self, position: &Position, depth: usize) -> Option<i32> {
+        metrics::inc(Counter::Searches);
+        let mut best = None;
+        let mut branches = 0;
+        for (brick_index, pos) in self.moves(&position.cells, position.hand) {
+            branches += 1;
+            let score = self.submove(position, brick_index, pos, depth, Parallelism::Off);
+            best = Some(best.map_or(score, |best: i32| best.max(score)));
+        }
+        metrics::add(Counter::SearchBranches, branches);
+        best
+    }
+
+    fn parallel_search(&self, position: &Position, depth: usize) -> Option<i32> {
+        metrics::inc(Counter::Searches);
+        let moves = self.moves(&position.cells, position.hand).collect::<Vec<_>>();
+        metrics::add(Counter::SearchBranches, moves.len() as u64);
+        moves
+            .par_iter()
+            .map(|&(brick_index, pos)| self.submove(position, brick_index, pos, depth, Parallelism::Off))
+            .max()
+    }
+}
+
+// The move with the best score after searching `depth` moves ahead (the last one if there
+// are more with the same score).
+pub fn best_move(
+    game_state: &GameState,
+    depth: usize,
+    evaluator: &Evaluator,
+    parallelism: Parallelism,
+) -> Result<PossibleMoveScore, Error> {
+    let (search, root) = match Search::new(game_state, evaluator) {
+        Some(search) => search,
+        None => return reference_search(game_state, depth, evaluator, parallelism),
+    };
+
+    let moves = search.moves(&root.cells, root.hand).collect::<Vec<_>>();
+    metrics::inc(Counter::Searches);
+    metrics::add(Counter::SearchBranches, moves.len() as u64);
+    let score = |&(brick_index, pos): &(usize, XY)| search.submove(&root, brick_index, pos, depth, Parallelism::Off);
+    let scores = match parallelism {
+        Parallelism::Root => moves.par_iter().map(score).collect::<Vec<_>>(),
+        Parallelism::Off => moves.iter().map(score).collect(),
+    };
+
+    moves
+        .into_iter()
+        .zip(scores)
+        .max_by_key(|(_, score)| *score)
+        .map(|((brick_index, pos), score)| PossibleMoveScore {
+            possible_move: PossibleMove {
+                brick: search.hand[brick_index],
+                brick_index: brick_index as i32,
+                pos,
+            },
+            score,
+        })
+        .ok_or(Error::NoLegalMoves)
+}
+
+// Score of the given move, searching `depth` moves ahead (including the move).
+pub fn score_move(
+    game_state: &GameState,
+    m: &PossibleMove,
+    depth: usize,
+    evaluator: &Evaluator,
+    parallelism: Parallelism,
+) -> Result<i32, Error> {
+    let (search, root) = match Search::new(game_state, evaluator) {
+        Some(search) => search,
+        None => return reference_submove_score(game_state, m, depth, evaluator, parallelism),
+    };
+
+    let brick_index = m.brick_index as usize;
+    let legal = m.brick_index >= 0
+        && brick_index < search.hand.len()
+        && can_put_brick(&game_state.board, search.hand[brick_index].brick(), &m.pos);
+    if !legal {
+        return Err(Error::IllegalMove(format!(
+            "brick {} can't be put at {},{}",
+            m.brick_index + 1,
+            m.pos.x,
+            m.pos.y
+        )));
+    }
+    Ok(search.submove(&root, brick_index, m.pos, depth, parallelism))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::ai::reference_submove_score;
+    use crate::brick::xy;
+    use crate::dealer::{BrickQueue, Dealer, SeededDealer};
+    use crate::feasibility::hand_is_fully_placeable;
+    use crate::game::{get_possible_moves, perform_move, RefillPolicy, Rules};
+    use crate::resolve::resolve_board;
+    use crate::testing;
+    use crate::Cell;
+    use proptest::prelude::*;
+    use rand::rngs::StdRng;
+    use rand::{Rng, SeedableRng};
+
+    // a position from a random game, with the given rules
+    fn random_position(seed: u64, board: Board, rules: Rules, preview: usize, moves: usize) -> GameState {
+        let mut rng = StdRng::seed_from_u64(seed);
+        let mut dealer = SeededDealer::new(seed);
+        let mut queue = BrickQueue::new(preview);
+        let mut game_state = GameState::with_rules(rules);
+        game_state.board = board;
+        for _ in 0..moves {
+            queue.refill(&mut game_state, &mut dealer);
+            let moves = get_possible_moves(&game_state);
+            if moves.is_empty() {
+                break;
+            }
+            let m = &moves[rng.gen_range(0, moves.len())];
+            game_state = perform_move(&game_state, &m.clone().into()).unwrap();
+        }
+        queue.refill(&mut game_state, &mut dealer);
+        game_state
+    }
+
+    #[test]
+    fn test_illegal_moves_are_errors() {
+        let mut game_state = GameState::new();
+        game_state.available_bricks = vec![BrickId::new(1, 0).unwrap()];
+        let m = |brick_index, pos| PossibleMove {
+            brick: game_state.available_bricks[0],
+            brick_index,
+            pos,
+        };
+        let evaluator = Evaluator::Heuristic;
+        assert!(score_move(&game_state, &m(0, xy(0, 0)), 2, &evaluator, Parallelism::Off).is_ok());
+        assert!(score_move(&game_state, &m(0, xy(8, 0)), 2, &evaluator, Parallelism::Off).is_err());
+        assert!(score_move(&game_state, &m(1, xy(0, 0)), 2, &evaluator, Parallelism::Off).is_err());
+        assert!(score_move(&game_state, &m(-1, xy(0, 0)), 2, &evaluator, Parallelism::Off).is_err());
+    }
+
+    #[test]
+    fn test_double_stone_cleared_twice() {
+        // the double stone is in the cleared row and box, so it doesn't stay on the board
+        let mut board = Board::default();
+        for x in 0..9 {
+            board.set(xy(x, 0), Cell::Filled);
+        }
+        board.set(xy(0, 0), Cell::DoubleStone);
+        board.set(xy(3, 0), Cell::DoubleStone);
+        for pos in &[xy(0, 1), xy(1, 1), xy(2, 1), xy(0, 2), xy(1, 2), xy(2, 2)] {
+            board.set(*pos, Cell::Filled);
+        }
+        let mut cells = Cells::new(&board);
+        let geometry = board.geometry();
+        assert_eq!(cells.resolve(geometry.region_masks()), 2);
+        assert_eq!(cells.board(geometry), resolve_board(board).board);
+        assert_eq!(cells.board(geometry).get(xy(0, 0)), Cell::Empty);
+        assert_eq!(cells.board(geometry).get(xy(3, 0)), Cell::Filled);
+    }
+
+    #[test]
+    fn test_parallelism_keeps_the_moves() {
+        let game_state = random_position(7, Board::default(), Rules::default(), 0, 6);
+        let evaluator = Evaluator::Heuristic;
+        let parallel = best_move(&game_state, 2, &evaluator, Parallelism::Root).unwrap();
+        let single_threaded = best_move(&game_state, 2, &evaluator, Parallelism::Off).unwrap();
+        let reference = reference_search(&game_state, 2, &evaluator, Parallelism::Off).unwrap();
+        assert_eq!(single_threaded.score, parallel.score);
+        assert_eq!(single_threaded.possible_move.pos, parallel.possible_move.pos);
+        assert_eq!(reference.score, parallel.score);
+    }
+
+    proptest! {
+        #![proptest_config(ProptestConfig::with_cases(16))]
+
+        // also on crowded boards, where the bricks don't just fit greedily
+        #[test]
+        fn test_fully_placeable_agrees_with_feasibility(
+            board in testing::board(),
+            seed in any::<u64>(),
+            count in 1..=4usize,
+        ) {
+            let hand = SeededDealer::new(seed).deal(&GameState::new(), count);
+            let game_state = GameState {
+                board,
+                available_bricks: hand.clone(),
+                ..GameState::new()
+            };
+            let evaluator = Evaluator::Heuristic;
+            let (search, root) = Search::new(&game_state, &evaluator).unwrap();
+            let expected = hand_is_fully_placeable(&board, &hand);
+            prop_assert_eq!(search.fully_placeable(&root.cells, &hand, root.hand), expected);
+        }
+
+        // a move changes the board and the points like `perform_move`
+        #[test]
+        fn test_play(board in testing::board(), seed in any::<u64>()) {
+            let game_state = random_position(seed, board, Rules::default(), 0, 0);
+            let evaluator = Evaluator::Heuristic;
+            let (search, root) = Search::new(&game_state, &evaluator).unwrap();
+            for m in get_possible_moves(&game_state) {
+                let expected = perform_move(&game_state, &m.clone().into()).unwrap();
+                let actual = search.play(&root, m.brick_index as usize, m.pos);
+                prop_assert_eq!(actual.cells.board(search.geometry), expected.board);
+                prop_assert_eq!(actual.points, expected.points);
+                prop_assert_eq!(actual.last_move_was_match, expected.last_move_was_match);
+            }
+        }
+
+        // the search core gives the same results as the reference search, with every rule
+        #[test]
+        fn test_same_results_as_the_reference(
+            seed in any::<u64>(),
+            board in testing::board(),
+            moves in 0..20usize,
+            every_move in any::<bool>(),
+            preview in 0..4usize,
+            depth in 1..=2usize,
+        ) {
+            let rules = Rules {
+                refill_policy: if every_move { RefillPolicy::AfterEveryMove } else { RefillPolicy::WhenEmpty },
+                ..Rules::default()
+            };
+            let game_state = random_position(seed, board, rules, preview, moves);
+            let evaluator = Evaluator::Heuristic;
+
+            let expected = reference_search(&game_state, depth, &evaluator, Parallelism::Root);
+            let actual = best_move(&game_state, depth, &evaluator, Parallelism::Root);
+            prop_assert_eq!(actual.is_ok(), expected.is_ok());
+            if let (Ok(actual), Ok(expected)) = (actual, expected) {
+                prop_assert_eq!(actual.score, expected.score);
+                prop_assert_eq!(actual.possible_move.brick_index, expected.possible_move.brick_index);
+                prop_assert_eq!(actual.possible_move.pos, expected.possible_move.pos);
+            }
+
+            for m in get_possible_moves(&game_state).iter().take(5) {
+                prop_assert_eq!(
+                    score_move(&game_state, m, depth, &evaluator, Parallelism::Root),
+                    reference_submove_score(&game_state, m, depth, &evaluator, Parallelism::Off)
+                );
+            }
+        }
+    }
+}
@@ -0,0 +1,366 @@
+use crate::ai::Evaluator;
+use crate::brick::BrickId;
+use crate::dealer::{BrickQueue, Dealer, DealerFactory, MAX_PREVIEW};
+use crate::game::{can_hold, get_possible_moves, is_game_over, perform_action, Action, GameState};
+use crate::game::{Move, RefillPolicy, Rules, MAX_HAND_SIZE};
+use crate::notation::{format_action, parse_action};
+use crate::search::Parallelism;
+use crate::Board;
+use serde_json::{json, Value};
+use std::io::{BufRead, BufReader, Write};
+use std::net::{TcpListener, TcpStream};
+use std::thread;
+
+// Games are served over TCP, one JSON message per line. Every connection is a separate session
+// playing a single game at a time. Requests:
+//   {"cmd": "new_game"}      - optionally with "hand_size", "refill" (`empty` / `every-move`), "hold" and "preview"
+//                              (hands of up to `MAX_HAND_SIZE` bricks, previews of up to `MAX_PREVIEW`, as far as
+//                              the server's dealer allows)
+//   {"cmd": "state"}
+//   {"cmd": "legal_moves"}
+//   {"cmd": "apply", "move": "3 d4"} - moves in the standard notation, including `hold 2`
+//   {"cmd": "undo"}
+// Responses are `{"ok": true, ...}` with the state or the moves, or `{"ok": false, "error": "..."}`.
+#[derive(Clone)]
+pub struct ServerConfig {
+    // defaults for new games
+    pub rules: Rules,
+    pub preview: usize,
+    pub start_board: Board,
+    // every session gets its own dealer
+    pub dealer: DealerFactory,
+    // used by dealers which search
+    pub evaluator: Evaluator,
+}
+
+impl ServerConfig {
+    fn new_dealer(&self) -> Box<dyn Dealer> {
+        (self.dealer)(&self.evaluator, Parallelism::Root)
+    }
+}
+
+pub fn serve(listener: TcpListener, config: ServerConfig) {
+    for stream in listener.incoming() {
+        match stream {
+            Ok(stream) => {
+                let config = config.clone();
+                thread::spawn(move || handle_connection(stream, config));
+            }
+            Err(e) => println!("can't accept a connection: {}", e),
+        }
+    }
+}
+
+fn handle_connection(stream: TcpStream, config: ServerConfig) {
+    let mut writer = match stream.try_clone() {
+        Ok(writer) => writer,
+        Err(e) => {
+            println!("can't set up a session: {}", e);
+            return;
+        }
+    };
+
+    let mut session = Session::new(config);
+    for line in BufReader::new(stream).lines().map_while(Result::ok) {
+        if line.trim().is_empty() {
+            continue;
+        }
+        let response = match serde_json::from_str::<Value>(&line) {
+            Ok(request) => session.handle(&request),
+            Err(e) => Err(format!("invalid JSON: {}", e)),
+        };
+        let response = match response {
+            Ok(mut response) => {
+                response["ok"] = json!(true);
+                response
+            }
+            Err(e) => json!({"ok": false, "error": e}),
+        };
+        if writeln!(writer, "{}", response).is_err() {
+            return;
+        }
+    }
+}
+
+pub struct Session {
+    config: ServerConfig,
+    game_state: GameState,
+    dealer: Box<dyn Dealer>,
+    queue: BrickQueue,
+    // states before the moves, for undoing them
+    history: Vec<(GameState, BrickQueue)>,
+}
+
+impl Session {
+    pub fn new(config: ServerConfig) -> Session {
+        let (rules, preview) = (config.rules, config.preview);
+        let mut session = Session {
+            dealer: config.new_dealer(),
+            config,
+            game_state: GameState::new(),
+            queue: BrickQueue::new(preview),
+            history: vec![],
+        };
+        session.new_game(rules, preview);
+        session
+    }
+
+    fn new_game(&mut self, rules: Rules, preview: usize) {
+        self.game_state = GameState::with_rules(rules);
+        self.game_state.board = self.config.start_board;
+        self.queue = BrickQueue::new(preview);
+        self.queue.refill(&mut self.game_state, self.dealer.as_mut());
+        self.history.clear();
+    }
+
+    pub fn handle(&mut self, request: &Value) -> Result<Value, String> {
+        match request["cmd"].as_str() {
+            Some("new_game") => {
+                let (rules, preview) = self.parse_game_options(request)?;
+                self.new_game(rules, preview);
+                Ok(self.state())
+            }
+            Some("state") => Ok(self.state()),
+            Some("legal_moves") => Ok(json!({ "moves": self.legal_moves() })),
+            Some("apply") => {
+                let text = request["move"].as_str().ok_or("`move` should be a string - e.g. `3 d4`")?;
+                if is_game_over(&self.game_state) {
+                    return Err("the game is over".to_string());
+                }
+                let action = parse_action(text, &self.game_state).map_err(|e| e.to_string())?;
+                let game_state = perform_action(&self.game_state, &action).map_err(|e| e.to_string())?;
+
+                self.history.push((self.game_state.clone(), self.queue.clone()));
+                self.game_state = game_state;
+                self.queue.refill(&mut self.game_state, self.dealer.as_mut());
+                Ok(self.state())
+            }
+            Some("undo") => {
+                let (game_state, queue) = self.history.pop().ok_or("there is no move to undo")?;
+                self.game_state = game_state;
+                self.queue = queue;
+                Ok(self.state())
+            }
+            Some(cmd) => Err(format!("unknown command: {}", cmd)),
+            None => Err("`cmd` should be a string".to_string()),
+        }
+    }
+
+    // options which aren't set are taken from the server's config
+    fn parse_game_options(&self, request: &Value) -> Result<(Rules, usize), String> {
+        let mut rules = self.config.rules;
+        let mut preview = self.config.preview;
+        if let Some(hand_size) = request.get("hand_size") {
+            rules.hand_size = match hand_size.as_u64() {
+                Some(hand_size) if hand_size > 0 && hand_size <= MAX_HAND_SIZE as u64 => hand_size as usize,
+                _ => return Err(format!("`hand_size` should be a number from 1 to {}", MAX_HAND_SIZE)),
+            };
+        }
+        if let Some(refill) = request.get("refill") {
+            rules.refill_policy = refill
+                .as_str()
+                .and_then(RefillPolicy::from_name)
+                .ok_or("`refill` should be `empty` or `every-move`")?;
+        }
+        if let Some(hold) = request.get("hold") {
+            rules.hold = hold.as_bool().ok_or("`hold` should be a boolean")?;
+        }
+        if let Some(value) = request.get("preview") {
+            preview = match value.as_u64() {
+                Some(preview) if preview <= MAX_PREVIEW as u64 => preview as usize,
+                _ => return Err(format!("`preview` should be a number from 0 to {}", MAX_PREVIEW)),
+            };
+        }
+        if preview > 0 && !self.dealer.supports_preview() {
+            return Err("the server's dealer picks the bricks against the current board, it can't be used with a preview"
+                .to_string());
+        }
+        if rules.hand_size > self.dealer.max_count() {
+            return Err(format!("the server's dealer deals hands of at most {} bricks", self.dealer.max_count()));
+        }
+        Ok((rules, preview))
+    }
+
+    fn state(&self) -> Value {
+        let game_state = &self.game_state;
+        let board = game_state
+            .board
+            .rows()
+            .map(|row| row.iter().map(|cell| cell.symbol()).collect::<String>())
+            .collect::<Vec<_>>();
+        json!({
+            "board": board,
+            "hand": bricks_json(&game_state.available_bricks),
+            "next": bricks_json(&game_state.upcoming_bricks),
+            "held": game_state.held_brick.as_ref().map(brick_json),
+            "points": game_state.points,
+            "game_over": is_game_over(game_state),
+            "moves_played": self.history.len(),
+        })
+    }
+
+    fn legal_moves(&self) -> Vec<String> {
+        let mut actions = get_possible_moves(&self.game_state)
+            .into_iter()
+            .map(|m| {
+                Action::Place(Move {
+                    brick_index: m.brick_index,
+                    pos: m.pos,
+                })
+            })
+            .collect::<Vec<_>>();
+        if can_hold(&self.game_state) {
+            for brick_index in 0..self.game_state.available_bricks.len() as i32 {
+                actions.push(Action::Hold { brick_index });
+            }
+        }
+        actions.iter().map(format_action).collect()
+    }
+}
+
+// bricks are their IDs and lists of their cells' [x, y] offsets - e.g. {"id": "1r0", "cells": [[0, 0], [1, 0]]}
+fn brick_json(id: &BrickId) -> Value {
+    let cells = id.brick().offsets().iter().map(|v| [v.x, v.y]).collect::<Vec<_>>();
+    json!({"id": id.to_string(), "cells": cells})
+}
+
+fn bricks_json(bricks: &[BrickId]) -> Value {
+    json!(bricks.iter().map(brick_json).collect::<Vec<_>>())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::dealer::{AdversarialDealer, RandomDealer, MAX_ADVERSARIAL_COUNT};
+    use std::net::SocketAddr;
+
+    fn config() -> ServerConfig {
+        ServerConfig {
+            rules: Rules::default(),
+            preview: 0,
+            start_board: Board::default(),
+            dealer: |_, _| Box::new(RandomDealer),
+            evaluator: Evaluator::Heuristic,
+        }
+    }
+
+    #[test]
+    fn test_new_game() {
+        let mut session = Session::new(config());
+        let state = session
+            .handle(&json!({"cmd": "new_game", "hand_size": 2, "preview": 1}))
+            .unwrap();
+        assert_eq!(state["hand"].as_array().unwrap().len(), 2);
+        assert_eq!(state["next"].as_array().unwrap().len(), 1);
+        let brick = &state["hand"][0];
+        let id = BrickId::parse(brick["id"].as_str().unwrap()).unwrap();
+        assert_eq!(brick["cells"].as_array().unwrap().len(), id.brick().offsets().len());
+        assert_eq!(state["board"][0], ".........");
+        assert_eq!(state["game_over"], false);
+    }
+
+    #[test]
+    fn test_apply_and_undo() {
+        let mut session = Session::new(config());
+        let before = session.handle(&json!({"cmd": "state"})).unwrap();
+
+        let moves = session.handle(&json!({"cmd": "legal_moves"})).unwrap();
+        let first_move = moves["moves"][0].as_str().unwrap().to_string();
+        let after = session.handle(&json!({"cmd": "apply", "move": first_move})).unwrap();
+        assert!(after["points"].as_i64().unwrap() > 0);
+        assert_eq!(after["moves_played"], 1);
+
+        assert_eq!(session.handle(&json!({"cmd": "undo"})).unwrap(), before);
+        assert!(session.handle(&json!({"cmd": "undo"})).is_err());
+    }
+
+    #[test]
+    fn test_invalid_requests() {
+        let mut session = Session::new(config());
+        assert!(session.handle(&json!({"cmd": "apply", "move": "9 a1"})).is_err());
+        assert!(session.handle(&json!({"cmd": "apply"})).is_err());
+        assert!(session.handle(&json!({"cmd": "fly"})).is_err());
+        assert!(session.handle(&json!({})).is_err());
+        assert!(session.handle(&json!({"cmd": "new_game", "refill": "never"})).is_err());
+    }
+
+    #[test]
+    fn test_out_of_range_options() {
+        let mut session = Session::new(config());
+        for request in &[
+            json!({"cmd": "new_game", "preview": 1_000_000_000_000_000_000u64}),
+            json!({"cmd": "new_game", "preview": MAX_PREVIEW + 1}),
+            json!({"cmd": "new_game", "hand_size": MAX_HAND_SIZE + 1}),
+            json!({"cmd": "new_game", "hand_size": 0}),
+        ] {
+            assert!(session.handle(request).is_err(), "{}", request);
+        }
+        let state = session
+            .handle(&json!({"cmd": "new_game", "hand_size": MAX_HAND_SIZE, "preview": MAX_PREVIEW}))
+            .unwrap();
+        assert_eq!(state["next"].as_array().unwrap().len(), MAX_PREVIEW);
+    }
+
+    #[test]
+    fn test_configured_dealer() {
+        struct SingleCellDealer;
+        impl Dealer for SingleCellDealer {
+            fn deal(&mut self, _: &GameState, count: usize) -> Vec<BrickId> {
+                vec![BrickId::new(0, 0).unwrap(); count]
+            }
+        }
+        let mut session = Session::new(ServerConfig {
+            dealer: |_, _| Box::new(SingleCellDealer),
+            ..config()
+        });
+        let state = session.handle(&json!({"cmd": "new_game", "preview": 2})).unwrap();
+        for brick in state["hand"].as_array().unwrap().iter().chain(state["next"].as_array().unwrap()) {
+            assert_eq!(brick["id"], "0r0");
+        }
+        assert_eq!(state["next"].as_array().unwrap().len(), 2);
+
+        // the options of new games are checked against the dealer
+        let mut session = Session::new(ServerConfig {
+            rules: Rules {
+                hand_size: 1,
+                ..Rules::default()
+            },
+            dealer: |evaluator, parallelism| Box::new(AdversarialDealer::new(2, evaluator.clone(), parallelism)),
+            ..config()
+        });
+        assert!(session.handle(&json!({"cmd": "new_game", "preview": 1})).is_err());
+        assert!(session.handle(&json!({"cmd": "new_game", "hand_size": MAX_ADVERSARIAL_COUNT + 1})).is_err());
+        let state = session.handle(&json!({"cmd": "new_game", "hand_size": 2})).unwrap();
+        assert_eq!(state["hand"].as_array().unwrap().len(), 2);
+    }
+
+    #[test]
+    fn test_serve_concurrent_sessions() {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let address: SocketAddr = listener.local_addr().unwrap();
+        thread::spawn(move || serve(listener, config()));
+
+        let connect = || {
+            let stream = TcpStream::connect(address).unwrap();
+            (stream.try_clone().unwrap(), BufReader::new(stream).lines())
+        };
+        let (mut first, mut first_responses) = connect();
+        let (mut second, mut second_responses) = connect();
+
+        writeln!(first, r#"{{"cmd": "undo"}}"#).unwrap();
+        writeln!(second, r#"{{"cmd": "state"}}"#).unwrap();
+        writeln!(first, "not json").unwrap();
+        writeln!(second, r#"{{"cmd": "new_game", "preview": 1000000000000000000}}"#).unwrap();
+        writeln!(second, r#"{{"cmd": "state"}}"#).unwrap();
+
+        let response = |lines: &mut std::io::Lines<BufReader<TcpStream>>| {
+            serde_json::from_str::<Value>(&lines.next().unwrap().unwrap()).unwrap()
+        };
+        assert_eq!(response(&mut first_responses)["ok"], false);
+        assert_eq!(response(&mut second_responses)["ok"], true);
+        assert_eq!(response(&mut first_responses)["ok"], false);
+        // a bad request doesn't end the session
+        assert_eq!(response(&mut second_responses)["ok"], false);
+        assert_eq!(response(&mut second_responses)["ok"], true);
+    }
+}
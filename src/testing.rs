@@ -0,0 +1,61 @@
+// Strategies for the property tests of the engine.
+use crate::brick::{brick_library, xy, Brick, XY};
+use crate::geometry::{parse_regions, Geometry};
+use crate::{Board, Cell};
+use lazy_static::lazy_static;
+use proptest::prelude::*;
+
+lazy_static! {
+    // all the kinds of boards: standard, without boxes, with big boxes, diagonals and jigsaw boxes
+    static ref GEOMETRIES: Vec<&'static Geometry> = vec![
+        Geometry::standard(),
+        Geometry::new(8, 0).unwrap().leak(),
+        Geometry::new(16, 4).unwrap().leak(),
+        parse_regions("diagonals", 9, 3).unwrap().leak(),
+        parse_regions("aabb\nacbb\naccd\n.ddd", 4, 2).unwrap().leak(),
+    ];
+}
+
+pub fn geometry() -> impl Strategy<Value = &'static Geometry> {
+    prop::sample::select(GEOMETRIES.clone())
+}
+
+// Boards are mostly filled (that's when regions get cleared) with some stones.
+pub fn board_with(geometry: &'static Geometry) -> impl Strategy<Value = Board> {
+    let cell = prop_oneof![
+        3 => Just(Cell::Empty),
+        6 => Just(Cell::Filled),
+        1 => Just(Cell::Stone),
+        1 => Just(Cell::DoubleStone),
+    ];
+    let cells = (geometry.size * geometry.size) as usize;
+    prop::collection::vec(cell, cells).prop_map(move |cells| {
+        let mut board = Board::new(geometry);
+        for (pos, cell) in board.positions().zip(cells) {
+            board.set(pos, cell);
+        }
+        board
+    })
+}
+
+pub fn board() -> impl Strategy<Value = Board> {
+    geometry().prop_flat_map(board_with)
+}
+
+pub fn library_brick() -> impl Strategy<Value = Brick> {
+    prop::sample::select(brick_library().bricks().to_vec())
+}
+
+// any shape fitting in a 3x3 square, normalized like the library bricks
+pub fn brick() -> impl Strategy<Value = Brick> {
+    prop::collection::btree_set((0..3, 0..3), 1..=9).prop_map(|cells| {
+        let min_x = cells.iter().map(|c| c.0).min().unwrap();
+        let min_y = cells.iter().map(|c| c.1).min().unwrap();
+        Brick::new(cells.iter().map(|&(x, y)| xy(x - min_x, y - min_y)).collect()).unwrap()
+    })
+}
+
+// positions slightly off the board too
+pub fn position(size: i32) -> impl Strategy<Value = XY> {
+    (-2..size + 2, -2..size + 2).prop_map(|(x, y)| xy(x, y))
+}
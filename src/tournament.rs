@@ -0,0 +1,176 @@
+// Statistics for comparing strategies which played the same games (the same bricks were dealt
+// to every strategy in a game), so scores can be compared game by game.
+
+#[derive(Debug, PartialEq)]
+pub struct Summary {
+    pub mean: f64,
+    pub median: f64,
+    pub std_dev: f64,
+    pub min: i32,
+    pub max: i32,
+}
+
+pub fn summary(scores: &[i32]) -> Summary {
+    assert!(!scores.is_empty());
+    let mut sorted = scores.to_vec();
+    sorted.sort_unstable();
+    let n = sorted.len();
+    let mean = sorted.iter().map(|&s| s as f64).sum::<f64>() / n as f64;
+    let median = if n % 2 == 1 {
+        sorted[n / 2] as f64
+    } else {
+        (sorted[n / 2 - 1] + sorted[n / 2]) as f64 / 2.0
+    };
+    let variance = sorted.iter().map(|&s| (s as f64 - mean).powi(2)).sum::<f64>() / n as f64;
+
+    Summary {
+        mean,
+        median,
+        std_dev: variance.sqrt(),
+        min: sorted[0],
+        max: sorted[n - 1],
+    }
+}
+
+#[derive(Debug, PartialEq)]
+pub struct HeadToHead {
+    pub wins: usize,
+    pub losses: usize,
+    pub draws: usize,
+    // two-sided sign test - probability of a result at least this uneven if both strategies
+    // were equally good
+    pub p_value: f64,
+}
+
+impl HeadToHead {
+    // draws count as half a win
+    pub fn win_rate(&self) -> f64 {
+        let games = self.wins + self.losses + self.draws;
+        (self.wins as f64 + self.draws as f64 / 2.0) / games.max(1) as f64
+    }
+}
+
+pub fn head_to_head(scores: &[i32], opponent_scores: &[i32]) -> HeadToHead {
+    assert_eq!(scores.len(), opponent_scores.len());
+    let wins = scores.iter().zip(opponent_scores).filter(|(a, b)| a > b).count();
+    let losses = scores.iter().zip(opponent_scores).filter(|(a, b)| a < b).count();
+    HeadToHead {
+        wins,
+        losses,
+        draws: scores.len() - wins - losses,
+        p_value: sign_test(wins, losses),
+    }
+}
+
+fn sign_test(wins: usize, losses: usize) -> f64 {
+    let n = wins + losses;
+    let k = wins.min(losses);
+    // P(X <= k) for X ~ Binomial(n, 1/2)
+    let mut probability = 0.5f64.powi(n as i32);
+    let mut cumulative = probability;
+    for i in 0..k {
+        probability *= (n - i) as f64 / (i + 1) as f64;
+        cumulative += probability;
+    }
+    (2.0 * cumulative).min(1.0)
+}
+
+// Elo-like ratings fitted to all the games at once (Bradley-Terry model), so they don't depend on
+// the order of the games. `scores[strategy][game]`. Every pair of strategies gets an extra draw,
+// so a strategy winning all its games still gets a finite rating. The average rating is 1500.
+pub fn ratings(scores: &[Vec<i32>]) -> Vec<f64> {
+    let n = scores.len();
+    // a lone strategy has nobody to be compared with
+    if n < 2 {
+        return vec![1500.0; n];
+    }
+    // wins[i][j] - points of `i` against `j`, draws count as half a win
+    let mut wins = vec![vec![0.5; n]; n];
+    for i in 0..n {
+        for j in 0..n {
+            if i == j {
+                wins[i][j] = 0.0;
+                continue;
+            }
+            for (a, b) in scores[i].iter().zip(&scores[j]) {
+                wins[i][j] += match a.cmp(b) {
+                    std::cmp::Ordering::Greater => 1.0,
+                    std::cmp::Ordering::Equal => 0.5,
+                    std::cmp::Ordering::Less => 0.0,
+                };
+            }
+        }
+    }
+
+    // minorization-maximization iterations
+    let mut strengths = vec![1.0; n];
+    for _ in 0..1000 {
+        let mut next = (0..n)
+            .map(|i| {
+                let total_wins = wins[i].iter().sum::<f64>();
+                let denominator = (0..n)
+                    .filter(|&j| j != i)
+                    .map(|j| (wins[i][j] + wins[j][i]) / (strengths[i] + strengths[j]))
+                    .sum::<f64>();
+                total_wins / denominator
+            })
+            .collect::<Vec<_>>();
+        let mean_log = next.iter().map(|s| s.ln()).sum::<f64>() / n as f64;
+        for s in &mut next {
+            *s /= mean_log.exp();
+        }
+        strengths = next;
+    }
+
+    strengths.iter().map(|s| 1500.0 + 400.0 * s.log10()).collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_summary() {
+        let s = summary(&[4, 1, 3, 2]);
+        assert_eq!(s.mean, 2.5);
+        assert_eq!(s.median, 2.5);
+        assert_eq!(s.min, 1);
+        assert_eq!(s.max, 4);
+        assert!((s.std_dev - 1.25f64.sqrt()).abs() < 1e-9);
+    }
+
+    #[test]
+    fn test_head_to_head() {
+        let result = head_to_head(&[10, 20, 30, 40], &[5, 25, 30, 0]);
+        assert_eq!((result.wins, result.losses, result.draws), (2, 1, 1));
+        assert_eq!(result.win_rate(), 0.625);
+        // 3 games with 2:1 - nothing unusual
+        assert_eq!(result.p_value, 1.0);
+    }
+
+    #[test]
+    fn test_sign_test() {
+        // 10 wins in 10 games: 2 * 1/1024
+        assert!((sign_test(10, 0) - 2.0 / 1024.0).abs() < 1e-12);
+        assert!((sign_test(1, 9) - 2.0 * 11.0 / 1024.0).abs() < 1e-12);
+        assert_eq!(sign_test(0, 0), 1.0);
+    }
+
+    #[test]
+    fn test_ratings() {
+        let scores = vec![vec![30, 30, 30, 30], vec![20, 20, 20, 20], vec![10, 10, 10, 30]];
+        let ratings = ratings(&scores);
+        assert!(ratings[0] > ratings[1]);
+        assert!(ratings[1] > ratings[2]);
+        assert!((ratings.iter().sum::<f64>() / 3.0 - 1500.0).abs() < 1e-6);
+
+        let equal = super::ratings(&[vec![1, 2], vec![1, 2]]);
+        assert!((equal[0] - equal[1]).abs() < 1e-9);
+    }
+
+    #[test]
+    fn test_ratings_single_strategy() {
+        assert_eq!(ratings(&[vec![10, 20]]), vec![1500.0]);
+        assert!(ratings(&[]).is_empty());
+    }
+}